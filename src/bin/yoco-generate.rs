@@ -0,0 +1,145 @@
+// Prints puzzle codes for boards of a chosen difficulty, one per line, for building practice
+// sets:
+//
+//     $ yoco-generate --clears 2 --moves 4 --count 10
+//
+// Each code ends with its target, and every puzzle needs at least the given number of moves to
+// reach it. Ranges like `--moves 2-3` are accepted too.
+
+use std::{ops::RangeInclusive, process, str::FromStr};
+
+use rand::{rngs::StdRng, SeedableRng};
+use yoco_test_kitchen::{
+    board::{Board, Piece},
+    generator::{self, Difficulty},
+    pieces::PieceDefinitions,
+};
+
+// How many boards are tried for each puzzle before giving up on the bounds
+const ATTEMPTS: usize = 2000;
+
+const USAGE: &str = "\
+Usage: yoco-generate [--clears <n>] [--moves <n>] [--count <n>] [--size <width>x<height>]
+                     [--pieces <n or letters>] [--seed <n>]
+
+Prints puzzle codes for boards that need the given number of moves, at the least, to reach the
+given number of clears. Numbers of clears and moves can also be ranges like 2-3.
+
+Options:
+  --clears <n>      How many clears the target asks for (default 2)
+  --moves <n>       How many moves the target takes (default 3)
+  --count <n>       How many puzzles to print (default 1)
+  --size <w>x<h>    The size of the board (default 5x5)
+  --pieces <n>      How many kinds of piece to use, at least 3, or their letters (default 6)
+  --seed <n>        Makes the same puzzles every time";
+
+struct Options {
+    difficulty: Difficulty,
+    count: usize,
+    width: usize,
+    height: usize,
+    pieces: Vec<Piece>,
+    seed: Option<u64>,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("yoco-generate: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let definitions = PieceDefinitions::from_ron(include_str!("../../assets/pieces.ron"))
+        .expect("the game's own piece definitions are valid");
+    let options = parse_args(std::env::args().skip(1), &definitions)?;
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    for _ in 0..options.count {
+        let puzzle = generator::generate(
+            options.width,
+            options.height,
+            &options.pieces,
+            &options.difficulty,
+            ATTEMPTS,
+            &mut rng,
+        )
+        .ok_or_else(|| {
+            format!(
+                "couldn't find a puzzle that fits after {} tries, try looser bounds",
+                ATTEMPTS
+            )
+        })?;
+        println!("{}", puzzle.to_code(&definitions));
+    }
+    Ok(())
+}
+
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+    definitions: &PieceDefinitions,
+) -> Result<Options, String> {
+    let mut options = Options {
+        difficulty: Difficulty::exactly(2, 3),
+        count: 1,
+        width: 5,
+        height: 5,
+        pieces: definitions.first(6),
+        seed: None,
+    };
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_owned(), Some(value.to_owned())),
+            None => (arg, None),
+        };
+        let value = || {
+            value
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+        match name.as_str() {
+            "--clears" => options.difficulty.clears = parse_range(&value()?)?,
+            "--moves" => options.difficulty.moves = parse_range(&value()?)?,
+            "--count" => options.count = parse_number(&value()?)?,
+            "--seed" => options.seed = Some(parse_number(&value()?)?),
+            "--size" => {
+                let size = value()?;
+                let (width, height) = size
+                    .split_once('x')
+                    .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                    .filter(|&(width, height)| Board::is_valid_size(width, height))
+                    .ok_or_else(|| format!("{:?} is not a supported board size", size))?;
+                options.width = width;
+                options.height = height;
+            }
+            "--pieces" => {
+                options.pieces = definitions.choose(&value()?).map_err(|e| e.to_string())?
+            }
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown option {}\n\n{}", name, USAGE)),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_number<T: FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{:?} is not a number", value))
+}
+
+// Either a single number, e.g. `3`, or a range, e.g. `2-4`
+fn parse_range<T: FromStr + PartialOrd>(value: &str) -> Result<RangeInclusive<T>, String> {
+    let (start, end) = value.split_once('-').unwrap_or((value, value));
+    let range = parse_number(start)?..=parse_number(end)?;
+    if range.is_empty() {
+        return Err(format!("{:?} is an empty range", value));
+    }
+    Ok(range)
+}
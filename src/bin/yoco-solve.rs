@@ -0,0 +1,267 @@
+// Works out the best that can be done with a board, without opening a window. The board is read
+// from stdin, either as a puzzle code or as a grid of piece names with one row per line:
+//
+//     $ echo MCDFG-HMCDF-GHMCD-FGHMC-DFGHM | yoco-solve --moves 3
+//     $ printf 'Mascot Donut Heart Green\n...' | yoco-solve
+//
+// It prints the most clears that can be reached within the move budget, the fewest moves that
+// reach them, and how hard that is to find.
+
+use std::{
+    fs,
+    io::{self, Read},
+    process,
+};
+
+use yoco_test_kitchen::{
+    board::{Board, Line, Move, Piece},
+    pieces::PieceDefinitions,
+    puzzle_code::Puzzle,
+    rating, solver,
+};
+
+// The same number of moves the game looks ahead for hints and par
+const DEFAULT_MOVES: usize = 3;
+
+const USAGE: &str = "\
+Usage: yoco-solve [--moves <n>] [--pieces <file>] < board
+
+Reads a board from stdin, either as a puzzle code like MCDFG-HMCDF-GHMCD-FGHMC-DFGHM or as
+a grid of piece names with one row per line, and prints the most clears that can be made
+within the move budget along with the moves that make them and a difficulty rating.
+
+Options:
+  --moves <n>       How many moves to look ahead (default 3)
+  --pieces <file>   Piece definitions to use instead of the game's own";
+
+struct Options {
+    moves: usize,
+    pieces: PieceDefinitions,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("yoco-solve: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let options = parse_args(std::env::args().skip(1))?;
+
+    let mut input = String::new();
+    io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|e| format!("couldn't read the board: {}", e))?;
+    let board = parse_board(&input, &options.pieces)?;
+
+    let (clears, moves) = solver::best_within(&board, options.moves);
+    println!("Board: {}", Puzzle::new(board).to_code(&options.pieces));
+    println!("Most clears within {} moves: {}", options.moves, clears);
+    if moves.is_empty() {
+        if clears == 0 {
+            println!("No moves make a clear");
+        } else {
+            println!("No moves needed");
+        }
+        return Ok(());
+    }
+    let rating = rating::rate(&board, options.moves);
+    println!(
+        "Difficulty: {:.1} (good moves per step: {:.1} of {}, shortest solutions: {}, traps: {})",
+        rating.score,
+        rating.branching,
+        solver::all_moves(&board).count(),
+        rating.solutions,
+        rating.traps
+    );
+    println!("Moves (rows count from the top, columns from the left):");
+    let mut after = board;
+    for (i, &mv) in moves.iter().enumerate() {
+        println!("  {}. {}", i + 1, notation(mv, board.height()));
+        after.apply(mv);
+    }
+    println!("After: {}", Puzzle::new(after).to_code(&options.pieces));
+    Ok(())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut moves = DEFAULT_MOVES;
+    let mut pieces = None;
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_owned(), Some(value.to_owned())),
+            None => (arg, None),
+        };
+        let value = || {
+            value
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+        match name.as_str() {
+            "--moves" => {
+                let value = value()?;
+                moves = value
+                    .parse()
+                    .map_err(|_| format!("{:?} is not a number of moves", value))?;
+            }
+            "--pieces" => {
+                let path = value()?;
+                let ron = fs::read_to_string(&path)
+                    .map_err(|e| format!("couldn't read {}: {}", path, e))?;
+                pieces = Some(
+                    PieceDefinitions::from_ron(&ron).map_err(|e| format!("{}: {}", path, e))?,
+                );
+            }
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown option {}\n\n{}", name, USAGE)),
+        }
+    }
+
+    let pieces = match pieces {
+        Some(pieces) => pieces,
+        None => PieceDefinitions::from_ron(include_str!("../../assets/pieces.ron"))
+            .expect("the game's own piece definitions are valid"),
+    };
+    Ok(Options { moves, pieces })
+}
+
+// Anything that names a piece by its full name is read as a grid of names, and anything else as a
+// puzzle code
+fn parse_board(input: &str, pieces: &PieceDefinitions) -> Result<Board, String> {
+    let by_name = |word: &str| {
+        pieces
+            .all()
+            .find(|&piece| pieces.get(piece).name.eq_ignore_ascii_case(word))
+    };
+    if input.split_whitespace().any(|word| by_name(word).is_some()) {
+        return parse_name_grid(input, pieces, by_name);
+    }
+    Puzzle::from_code(input.trim(), pieces)
+        .map(|puzzle| puzzle.board)
+        .map_err(|e| e.to_string())
+}
+
+fn parse_name_grid(
+    input: &str,
+    pieces: &PieceDefinitions,
+    by_name: impl Fn(&str) -> Option<Piece>,
+) -> Result<Board, String> {
+    // Rows from the top down, the way they're written
+    let rows = input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.split_whitespace()
+                .map(|word| {
+                    // Single letters are let through too, so names and letters can be mixed
+                    let letter = word.chars().next().filter(|_| word.len() == 1);
+                    by_name(word)
+                        .or_else(|| letter.and_then(|c| pieces.from_code_char(c)))
+                        .ok_or_else(|| format!("{:?} is not a piece", word))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let height = rows.len();
+    let width = rows.first().map_or(0, |row| row.len());
+    if let Some((i, row)) = rows.iter().enumerate().find(|(_, row)| row.len() != width) {
+        return Err(format!(
+            "row {} has {} pieces but the first row has {}",
+            i + 1,
+            row.len(),
+            width
+        ));
+    }
+    if !Board::is_valid_size(width, height) {
+        return Err(format!("{}x{} is not a supported board size", width, height));
+    }
+    Ok(Board::from_fn(width, height, |x, y| rows[height - 1 - y][x]))
+}
+
+// e.g. "row 2 right 1" or "column 4 down 2", counting rows from the top and columns from the
+// left, starting at 1
+fn notation(mv: Move, height: usize) -> String {
+    let (line, index, direction) = match mv.line {
+        Line::Row(y) => ("row", height - y, if mv.steps > 0 { "right" } else { "left" }),
+        Line::Col(x) => ("column", x + 1, if mv.steps > 0 { "up" } else { "down" }),
+    };
+    format!("{} {} {} {}", line, index, direction, mv.steps.unsigned_abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yoco_test_kitchen::puzzle_code::PuzzleCodeError;
+
+    fn pieces() -> PieceDefinitions {
+        PieceDefinitions::from_ron(include_str!("../../assets/pieces.ron")).unwrap()
+    }
+
+    #[test]
+    fn reads_grids_of_names_the_same_as_puzzle_codes() {
+        let pieces = pieces();
+        let code = parse_board("MCDFG-HMCDF-GHMCD-FGHMC\n", &pieces).unwrap();
+        let grid = parse_board(
+            "Mascot Checkered Donut Flower Green\n\
+             heart mascot checkered donut flower\n\
+             \n\
+             Green Heart M C Donut\n\
+             Flower Green Heart Mascot C\n",
+            &pieces,
+        )
+        .unwrap();
+        assert_eq!(grid, code);
+        assert_eq!((grid.width(), grid.height()), (5, 4));
+        // The first row written is the top of the board
+        assert_eq!(pieces.code_char(grid.get(0, 3)), 'M');
+        assert_eq!(pieces.code_char(grid.get(4, 0)), 'C');
+    }
+
+    #[test]
+    fn rejects_boards_that_cant_be_read() {
+        let pieces = pieces();
+        let error = |input: &str| parse_board(input, &pieces).unwrap_err();
+        assert_eq!(
+            error("Mascot Donut Heart Green\nMascot Donut Heart\nM C D F\nM C D F"),
+            "row 2 has 3 pieces but the first row has 4"
+        );
+        assert_eq!(
+            error("Mascot Donut Heart Green\nM C D X\nM C D F\nM C D F"),
+            "\"X\" is not a piece"
+        );
+        assert_eq!(
+            error("Mascot Donut Heart\nM C D\nM C D"),
+            "3x3 is not a supported board size"
+        );
+        assert_eq!(
+            error("MCDFGHMCDFGHMCD"),
+            PuzzleCodeError::NotSquare(15).to_string()
+        );
+        assert_eq!(
+            error("MCDF-GHMC-DFGH-MCDX"),
+            PuzzleCodeError::UnknownPiece('X').to_string()
+        );
+        assert_eq!(
+            error("MCDF-GHMC-DFG-MCDF"),
+            PuzzleCodeError::RaggedRows {
+                row: 3,
+                expected: 4,
+                found: 3
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn moves_are_written_from_the_top_left() {
+        assert_eq!(notation(Move::new(Line::Row(3), 1), 4), "row 1 right 1");
+        assert_eq!(notation(Move::new(Line::Row(0), -2), 4), "row 4 left 2");
+        assert_eq!(notation(Move::new(Line::Col(0), 1), 4), "column 1 up 1");
+        assert_eq!(notation(Move::new(Line::Col(4), -1), 5), "column 5 down 1");
+    }
+}
@@ -0,0 +1,393 @@
+// The rules of the game, without any of the rendering. Everything in here can be driven without
+// spinning up a bevy App.
+
+use serde::{Deserialize, Serialize};
+
+/// The smallest number of rows or columns a board can have
+pub const MIN_BOARD_SIZE: usize = 4;
+/// The largest number of rows or columns a board can have
+pub const MAX_BOARD_SIZE: usize = 8;
+
+/// A kind of piece. What each kind is called and how it's drawn comes from the piece definitions
+/// in `crate::pieces`; the board only needs to tell them apart.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    not(target_arch = "wasm32"),
+    derive(bevy_reflect::Reflect, bevy_reflect::FromReflect)
+)]
+pub struct Piece(u8);
+
+impl Piece {
+    /// The kind of piece at `index` in the piece definitions
+    pub fn new(index: usize) -> Self {
+        Piece(index.try_into().expect("too many kinds of piece"))
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A single row or column of the board.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Line {
+    Row(usize),
+    Col(usize),
+}
+
+/// Rotation of a line by some number of steps, using the same sign convention as
+/// `Board::rotate_row` and `Board::rotate_col`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Move {
+    pub line: Line,
+    pub steps: isize,
+}
+
+impl Move {
+    pub fn new(line: Line, steps: isize) -> Self {
+        Move { line, steps }
+    }
+
+    /// The move that undoes this one
+    pub fn inverse(self) -> Self {
+        Move {
+            line: self.line,
+            steps: -self.steps,
+        }
+    }
+}
+
+/// A line that gets cleared, and the step of the cascade that clears it. Step 0 is made up of
+/// the lines that are already full; each later step only matches once the lines from the steps
+/// before it have been removed. Rows are checked before columns within a step, so a column can
+/// also be freed up by a row from its own step.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Clear {
+    pub line: Line,
+    pub step: usize,
+}
+
+/// A fully populated board. Indexed as `[y][x]`, with y = 0 being the bottom row and x = 0 being
+/// the leftmost column.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Board {
+    width: usize,
+    height: usize,
+    // Boards are stored at the largest possible size so that they stay cheap to copy, which the
+    // solver does a lot of. Anything past `width` and `height` is always left as the first kind of piece.
+    pieces: [[Piece; MAX_BOARD_SIZE]; MAX_BOARD_SIZE],
+}
+
+impl Board {
+    /// Builds a `width` by `height` board, calling `piece_at(x, y)` for each piece.
+    ///
+    /// Panics if either dimension is outside of `MIN_BOARD_SIZE..=MAX_BOARD_SIZE`.
+    pub fn from_fn(
+        width: usize,
+        height: usize,
+        mut piece_at: impl FnMut(usize, usize) -> Piece,
+    ) -> Self {
+        assert!(
+            Board::is_valid_size(width, height),
+            "{}x{} is not a valid board size",
+            width,
+            height
+        );
+        let mut pieces = [[Piece::default(); MAX_BOARD_SIZE]; MAX_BOARD_SIZE];
+        for (y, row) in pieces.iter_mut().enumerate().take(height) {
+            for (x, piece) in row.iter_mut().enumerate().take(width) {
+                *piece = piece_at(x, y);
+            }
+        }
+        Board {
+            width,
+            height,
+            pieces,
+        }
+    }
+
+    pub fn is_valid_size(width: usize, height: usize) -> bool {
+        let sizes = MIN_BOARD_SIZE..=MAX_BOARD_SIZE;
+        sizes.contains(&width) && sizes.contains(&height)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Each row of the board, starting from the bottom
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[Piece]> {
+        self.pieces[..self.height]
+            .iter()
+            .map(|row| &row[..self.width])
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Piece {
+        self.pieces[y][x]
+    }
+
+    /// Every row followed by every column
+    pub fn lines(&self) -> impl Iterator<Item = Line> {
+        (0..self.height)
+            .map(Line::Row)
+            .chain((0..self.width).map(Line::Col))
+    }
+
+    /// Number of pieces in a line
+    pub fn line_len(&self, line: Line) -> usize {
+        match line {
+            Line::Row(_) => self.width,
+            Line::Col(_) => self.height,
+        }
+    }
+
+    /// The pieces in a line, in order of increasing x or y
+    pub fn line_pieces(&self, line: Line) -> Vec<Piece> {
+        match line {
+            Line::Row(y) => self.pieces[y][..self.width].to_vec(),
+            Line::Col(x) => self.rows().map(|row| row[x]).collect(),
+        }
+    }
+
+    /// Rotates row `y` by `steps`. Positive steps move pieces towards higher x, wrapping the
+    /// rightmost pieces around to the left edge.
+    pub fn rotate_row(&mut self, y: usize, steps: isize) {
+        let steps = steps.rem_euclid(self.width as isize) as usize;
+        self.pieces[y][..self.width].rotate_right(steps);
+    }
+
+    /// Rotates column `x` by `steps`. Positive steps move pieces towards higher y, wrapping the
+    /// topmost pieces around to the bottom edge.
+    pub fn rotate_col(&mut self, x: usize, steps: isize) {
+        let mut col = self.line_pieces(Line::Col(x));
+        col.rotate_right(steps.rem_euclid(self.height as isize) as usize);
+        for (y, piece) in col.into_iter().enumerate() {
+            self.pieces[y][x] = piece;
+        }
+    }
+
+    pub fn apply(&mut self, mv: Move) {
+        match mv.line {
+            Line::Row(y) => self.rotate_row(y, mv.steps),
+            Line::Col(x) => self.rotate_col(x, mv.steps),
+        }
+    }
+
+    /// Returns true if any row or column is made up of a single kind of piece.
+    pub fn has_clear(&self) -> bool {
+        self.lines().any(|line| self.is_full_line(line))
+    }
+
+    /// Every row and column that's made up of a single kind of piece, rows first. Unlike
+    /// `count_clears`, this doesn't look any further ahead than the board as it is.
+    pub fn full_lines(&self) -> Vec<Line> {
+        self.lines()
+            .filter(|&line| self.is_full_line(line))
+            .collect()
+    }
+
+    fn is_full_line(&self, line: Line) -> bool {
+        self.line_pieces(line).into_iter().all_equal()
+    }
+
+    /// Counts the number of rows and columns that would be cleared, including the cascade of
+    /// lines that only match once earlier clears have been removed.
+    pub fn count_clears(&self) -> u8 {
+        let mut cnt = 0;
+        self.cascade(|_| cnt += 1);
+        cnt
+    }
+
+    /// Every row and column that would be cleared, in the order the cascade finds them.
+    pub fn clears(&self) -> Vec<Clear> {
+        let mut clears = vec![];
+        self.cascade(|clear| clears.push(clear));
+        clears
+    }
+
+    // Works through the cascade, calling `on_clear` for each line as it's cleared. Doesn't
+    // allocate, since the solver counts clears for every board it looks at.
+    fn cascade(&self, mut on_clear: impl FnMut(Clear)) {
+        let all_rows: u32 = (1 << self.height) - 1;
+        let all_cols: u32 = (1 << self.width) - 1;
+
+        let mut ignored_rows: u32 = 0;
+        let mut ignored_cols: u32 = 0;
+
+        for step in 0.. {
+            let prev_ignored = (ignored_rows, ignored_cols);
+            for nrow in 0..self.height {
+                if ignored_rows & (1 << nrow) != 0 {
+                    continue;
+                }
+                let all_eq = (0..self.width)
+                    .filter(|ncol| ignored_cols & (1 << ncol) == 0)
+                    .map(|ncol| self.pieces[nrow][ncol])
+                    .all_equal();
+                if all_eq {
+                    on_clear(Clear {
+                        line: Line::Row(nrow),
+                        step,
+                    });
+                    ignored_rows |= 1 << nrow;
+                }
+            }
+            for ncol in 0..self.width {
+                if ignored_cols & (1 << ncol) != 0 {
+                    continue;
+                }
+                let all_eq = (0..self.height)
+                    .filter(|nrow| ignored_rows & (1 << nrow) == 0)
+                    .map(|nrow| self.pieces[nrow][ncol])
+                    .all_equal();
+                if all_eq {
+                    on_clear(Clear {
+                        line: Line::Col(ncol),
+                        step,
+                    });
+                    ignored_cols |= 1 << ncol;
+                }
+            }
+
+            // We're faking a do-while here
+            if prev_ignored == (ignored_rows, ignored_cols)
+                || ignored_rows == all_rows
+                || ignored_cols == all_cols
+            {
+                return;
+            }
+        }
+    }
+}
+
+trait IteratorExt: Iterator {
+    fn all_equal(&mut self) -> bool
+    where
+        Self: Sized,
+        Self::Item: PartialEq,
+    {
+        match self.next() {
+            None => true,
+            Some(a) => self.all(|x| a == x),
+        }
+    }
+}
+impl<I: Iterator> IteratorExt for I {}
+
+/// Builds a board for tests from its rows, written top row first the way a puzzle code reads, with
+/// 'A' as the first kind of piece, 'B' the second and so on
+#[cfg(test)]
+pub(crate) fn test_board(rows: &[&str]) -> Board {
+    let rows: Vec<Vec<Piece>> = rows
+        .iter()
+        .map(|row| {
+            row.bytes()
+                .map(|c| Piece::new((c - b'A') as usize))
+                .collect()
+        })
+        .collect();
+    let height = rows.len();
+    Board::from_fn(rows[0].len(), height, |x, y| rows[height - 1 - y][x])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(pieces: Vec<Piece>) -> Vec<usize> {
+        pieces.into_iter().map(Piece::index).collect()
+    }
+
+    #[test]
+    fn rotate_row_wraps_around() {
+        let mut b = test_board(&["AAAAA", "AAAAA", "AAAAA", "ABCDE"]);
+        b.rotate_row(0, 1);
+        assert_eq!(indices(b.line_pieces(Line::Row(0))), [4, 0, 1, 2, 3]);
+        b.rotate_row(0, -3);
+        assert_eq!(indices(b.line_pieces(Line::Row(0))), [2, 3, 4, 0, 1]);
+        // A whole turn and then some is the same as the some
+        b.rotate_row(0, 7);
+        assert_eq!(indices(b.line_pieces(Line::Row(0))), [0, 1, 2, 3, 4]);
+        b.rotate_row(0, -11);
+        assert_eq!(indices(b.line_pieces(Line::Row(0))), [1, 2, 3, 4, 0]);
+        // The rest of the board is left alone
+        assert_eq!(indices(b.line_pieces(Line::Row(1))), [0; 5]);
+    }
+
+    #[test]
+    fn rotate_col_wraps_around() {
+        // 5 wide and 4 tall, with column 2 reading A, B, C, D from the bottom up
+        let mut b = test_board(&["AADAA", "AACAA", "AABAA", "AAAAA"]);
+        b.rotate_col(2, 1);
+        assert_eq!(indices(b.line_pieces(Line::Col(2))), [3, 0, 1, 2]);
+        b.rotate_col(2, -2);
+        assert_eq!(indices(b.line_pieces(Line::Col(2))), [1, 2, 3, 0]);
+        b.rotate_col(2, 5);
+        assert_eq!(indices(b.line_pieces(Line::Col(2))), [0, 1, 2, 3]);
+        b.rotate_col(2, -9);
+        assert_eq!(indices(b.line_pieces(Line::Col(2))), [1, 2, 3, 0]);
+        assert_eq!(indices(b.line_pieces(Line::Col(1))), [0; 4]);
+    }
+
+    #[test]
+    fn moves_are_undone_by_their_inverse() {
+        let start = test_board(&["ABCD", "BCDA", "CDAB", "DABC", "ABCD"]);
+        for mv in [
+            Move::new(Line::Row(4), 3),
+            Move::new(Line::Col(3), -2),
+            Move::new(Line::Col(0), 7),
+        ] {
+            let mut b = start;
+            b.apply(mv);
+            assert_ne!(b, start);
+            b.apply(mv.inverse());
+            assert_eq!(b, start);
+        }
+    }
+
+    #[test]
+    fn finds_full_lines() {
+        let none = test_board(&["ABCD", "BCDA", "CDAB", "DABC"]);
+        assert!(!none.has_clear());
+        assert!(none.full_lines().is_empty());
+        assert_eq!(none.count_clears(), 0);
+
+        let b = test_board(&["CCCCC", "ABCDB", "ADCBA", "ABCAD"]);
+        assert!(b.has_clear());
+        assert_eq!(b.full_lines(), [Line::Row(3), Line::Col(2)]);
+    }
+
+    #[test]
+    fn counts_cascading_clears() {
+        // Column 0 is full to start with. With it gone row 1 matches, which frees up column 1,
+        // and with both columns gone row 0 matches too.
+        let b = test_board(&["ACDB", "ACBD", "ABBB", "ACDD"]);
+        assert_eq!(b.full_lines(), [Line::Col(0)]);
+        assert_eq!(
+            b.clears(),
+            [
+                Clear {
+                    line: Line::Col(0),
+                    step: 0
+                },
+                Clear {
+                    line: Line::Row(1),
+                    step: 1
+                },
+                Clear {
+                    line: Line::Col(1),
+                    step: 1
+                },
+                Clear {
+                    line: Line::Row(0),
+                    step: 2
+                },
+            ]
+        );
+        assert_eq!(b.count_clears(), 4);
+    }
+}
@@ -0,0 +1,534 @@
+// The campaign: packs of fixed boards, each with a goal, played from the level select screen.
+// Picking a level puts its board in play through the same route as a pasted puzzle code, and the
+// goal is checked every time a slide finishes. Completing a level unlocks the next one, and which
+// levels have been completed is saved between runs.
+//
+// A level is over as soon as a different board is put in play, whether that's a new random board,
+// a loaded puzzle or undoing back past the start of the level.
+
+use std::collections::BTreeMap;
+
+use bevy::{prelude::*, time::Stopwatch};
+use bevy_tweening::TweenCompleted;
+use serde::{Deserialize, Serialize};
+use yoco_test_kitchen::levels::{Goal, Level};
+
+use crate::{
+    cascade::Cascade,
+    controls::{Action, Actions},
+    loading::Levels,
+    move_count_change,
+    sharing::{show_status, LoadPuzzle, StatusText},
+    stats::NewBoard,
+    storage,
+    time_attack::TimeAttack,
+    BoardState,
+};
+
+// How many lines of the level list the level select screen shows at once
+const VISIBLE_LINES: usize = 14;
+
+const STORAGE_NAME: &str = "progress";
+
+/// The fewest moves each completed level has been completed in, keyed by the names of the pack
+/// and the level
+#[derive(Resource, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Progress(BTreeMap<String, u32>);
+
+impl Progress {
+    fn load() -> Self {
+        let Some(saved) = storage::read(STORAGE_NAME) else {
+            return Progress::default();
+        };
+        ron::from_str(&saved).unwrap_or_else(|e| {
+            warn!("Ignoring saved progress, it couldn't be read: {}", e);
+            Progress::default()
+        })
+    }
+
+    fn save(&self) {
+        let saved = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .expect("progress can always be written as RON");
+        if let Err(e) = storage::write(STORAGE_NAME, &saved) {
+            warn!("Couldn't save progress: {}", e);
+        }
+    }
+
+    fn key(levels: &Levels, pack: usize, level: usize) -> String {
+        format!(
+            "{}/{}",
+            levels.packs[pack].name,
+            levels.level(pack, level).name
+        )
+    }
+
+    fn best_moves(&self, levels: &Levels, pack: usize, level: usize) -> Option<u32> {
+        self.0.get(&Progress::key(levels, pack, level)).copied()
+    }
+
+    fn is_unlocked(&self, levels: &Levels, pack: usize, level: usize) -> bool {
+        levels.is_unlocked(pack, level, |pack, level| {
+            self.best_moves(levels, pack, level).is_some()
+        })
+    }
+
+    fn complete(&mut self, levels: &Levels, pack: usize, level: usize, moves: u32) {
+        let best = self
+            .0
+            .entry(Progress::key(levels, pack, level))
+            .or_insert(moves);
+        *best = (*best).min(moves);
+        self.save();
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+enum Outcome {
+    Won,
+    OutOfMoves,
+    OutOfTime,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+enum LevelSelectPhase {
+    // No campaign screen is showing
+    #[default]
+    Off,
+    // Showing the list of levels
+    Select,
+    // Showing how the last level went
+    Finished(Outcome),
+}
+
+#[derive(Resource, Debug, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct LevelSelect {
+    phase: LevelSelectPhase,
+    // Index into Levels::levels() of the level picked on the screen, which is also the level
+    // that was just finished on the result screen
+    selected: usize,
+}
+
+impl LevelSelect {
+    pub fn is_open(&self) -> bool {
+        self.phase != LevelSelectPhase::Off
+    }
+}
+
+/// The level being played
+#[derive(Debug)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct LevelInPlay {
+    pack: usize,
+    level: usize,
+    moves: u32,
+    elapsed: Stopwatch,
+}
+
+#[derive(Resource, Debug, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct CurrentLevel {
+    playing: Option<LevelInPlay>,
+    // A level that's been picked, but whose board hasn't been put in play yet
+    starting: Option<(usize, usize)>,
+    // The moves and time the last level finished with, for the result screen
+    result: (u32, u64),
+    // Whether cascade mode was on before levels took over, so it can be put back once they're
+    // done with
+    cascade_before: Option<bool>,
+}
+
+impl CurrentLevel {
+    // Puts cascade mode back the way it was, unless another level is about to take over
+    fn restore_cascade(&mut self, cascade: &mut Cascade) {
+        if self.playing.is_some() || self.starting.is_some() {
+            return;
+        }
+        if let Some(enabled) = self.cascade_before.take() {
+            cascade.enabled = enabled;
+        }
+    }
+
+    /// Gives up on any level in play, for when something else is taking over the board and the
+    /// cascade setting, so neither gets changed back underneath it
+    pub fn abandon(&mut self) {
+        self.playing = None;
+        self.starting = None;
+        self.cascade_before = None;
+    }
+}
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct LevelSelectOverlay;
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct LevelSelectOverlayText;
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct LevelText;
+
+pub fn setup_campaign(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Progress::load());
+
+    let text_style = TextStyle {
+        font: asset_server.load("FiraSans-Bold.ttf"),
+        font_size: 24.0,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            LevelSelectOverlay,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("", text_style.clone()),
+                LevelSelectOverlayText,
+            ));
+        });
+
+    commands.spawn((
+        TextBundle::from_section("", text_style).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(0.0),
+                right: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        LevelText,
+    ));
+}
+
+// Moves between the level list, the levels themselves and the result screens
+#[allow(clippy::too_many_arguments)]
+pub fn level_select(
+    keys: Res<Input<KeyCode>>,
+    // Actions are blocked by the screen, so they're read before it can be changed
+    mut actions_and_select: ParamSet<(Actions, ResMut<LevelSelect>)>,
+    time_attack: Res<TimeAttack>,
+    levels: Res<Levels>,
+    progress: Res<Progress>,
+    mut current: ResMut<CurrentLevel>,
+    mut cascade: ResMut<Cascade>,
+    mut load_puzzles: EventWriter<LoadPuzzle>,
+    mut status_query: Query<&mut Text, With<StatusText>>,
+) {
+    let all: Vec<_> = levels
+        .levels()
+        .map(|(pack, level, _)| (pack, level))
+        .collect();
+    if all.is_empty() {
+        return;
+    }
+    let pressed = actions_and_select.p0().just_pressed(Action::LevelSelect);
+    let mut select = actions_and_select.p1();
+    let mut start = None;
+
+    match select.phase {
+        LevelSelectPhase::Off => {
+            // Time attack has screens of its own, and starting a level would end the round
+            if pressed && !time_attack.is_active() {
+                // Start from the level being played, or else the first one still to do
+                select.selected = match &current.playing {
+                    Some(playing) => all
+                        .iter()
+                        .position(|&level| level == (playing.pack, playing.level))
+                        .unwrap(),
+                    None => all
+                        .iter()
+                        .position(|&(pack, level)| {
+                            progress.is_unlocked(&levels, pack, level)
+                                && progress.best_moves(&levels, pack, level).is_none()
+                        })
+                        .unwrap_or(0),
+                };
+                select.phase = LevelSelectPhase::Select;
+            }
+        }
+        LevelSelectPhase::Select => {
+            if keys.just_pressed(KeyCode::Up) {
+                select.selected = (select.selected + all.len() - 1) % all.len();
+            } else if keys.just_pressed(KeyCode::Down) {
+                select.selected = (select.selected + 1) % all.len();
+            } else if keys.just_pressed(KeyCode::Return) {
+                let (pack, level) = all[select.selected];
+                if progress.is_unlocked(&levels, pack, level) {
+                    start = Some(select.selected);
+                }
+            } else if keys.just_pressed(KeyCode::Escape) {
+                select.phase = LevelSelectPhase::Off;
+            }
+        }
+        LevelSelectPhase::Finished(outcome) => {
+            if keys.just_pressed(KeyCode::Return) {
+                if outcome != Outcome::Won {
+                    start = Some(select.selected);
+                } else {
+                    // On to the next level if it's open, or back to the list if it isn't
+                    let next = select.selected + 1;
+                    match all.get(next) {
+                        Some(&(pack, level)) if progress.is_unlocked(&levels, pack, level) => {
+                            start = Some(next);
+                        }
+                        _ => {
+                            select.selected = next.min(all.len() - 1);
+                            select.phase = LevelSelectPhase::Select;
+                        }
+                    }
+                }
+            } else if keys.just_pressed(KeyCode::Escape) {
+                select.phase = LevelSelectPhase::Off;
+            }
+        }
+    }
+
+    let Some(index) = start else {
+        return;
+    };
+    let (pack, level) = all[index];
+    let chosen = levels.level(pack, level);
+    select.selected = index;
+    select.phase = LevelSelectPhase::Off;
+    current.starting = Some((pack, level));
+    // Cascades would clear the lines away before the goal could see them
+    current.cascade_before.get_or_insert(cascade.enabled);
+    cascade.enabled = false;
+    load_puzzles.send(LoadPuzzle(chosen.puzzle()));
+    show_status(
+        &mut status_query,
+        &format!("{}: {}", chosen.name, describe_goal(&chosen.goal)),
+    );
+}
+
+// Starts and ends levels as boards come and go, and checks the goal each time a slide finishes
+#[allow(clippy::too_many_arguments)]
+pub fn play_level(
+    mut new_boards: EventReader<NewBoard>,
+    mut slides: EventReader<TweenCompleted>,
+    time: Res<Time>,
+    levels: Res<Levels>,
+    board_state: Res<BoardState>,
+    mut progress: ResMut<Progress>,
+    mut select: ResMut<LevelSelect>,
+    mut current: ResMut<CurrentLevel>,
+    mut cascade: ResMut<Cascade>,
+) {
+    // Undoing a move gives it back, so undo can't be used to run out of moves or pad out the count
+    let changes: Vec<i32> = slides.iter().map(move_count_change).collect();
+    let slid = changes.iter().any(|&change| change != 0);
+    if let Some(NewBoard(board)) = new_boards.iter().last() {
+        current.playing = match current.starting.take() {
+            Some((pack, level)) if levels.level(pack, level).board == *board => Some(LevelInPlay {
+                pack,
+                level,
+                moves: 0,
+                elapsed: Stopwatch::new(),
+            }),
+            _ => None,
+        };
+        current.restore_cascade(&mut cascade);
+        return;
+    }
+
+    // The clock stops while a screen is covering the board
+    if select.is_open() {
+        return;
+    }
+    let current = &mut *current;
+    let Some(playing) = &mut current.playing else {
+        return;
+    };
+    let goal = levels.level(playing.pack, playing.level).goal;
+    playing.elapsed.tick(time.delta());
+    for change in changes {
+        playing.moves = playing.moves.saturating_add_signed(change);
+    }
+
+    let mut outcome = None;
+    if slid {
+        if let Some(board) = board_state.board() {
+            if goal.is_met(&board) {
+                outcome = Some(Outcome::Won);
+            } else if goal.moves.is_some_and(|moves| playing.moves >= moves) {
+                outcome = Some(Outcome::OutOfMoves);
+            }
+        }
+    }
+    if outcome.is_none()
+        && goal
+            .seconds
+            .is_some_and(|seconds| playing.elapsed.elapsed_secs() >= seconds as f32)
+    {
+        outcome = Some(Outcome::OutOfTime);
+    }
+    let Some(outcome) = outcome else {
+        return;
+    };
+
+    let playing = current.playing.take().unwrap();
+    current.result = (playing.moves, playing.elapsed.elapsed().as_secs());
+    if outcome == Outcome::Won {
+        progress.complete(&levels, playing.pack, playing.level, playing.moves);
+    }
+    current.restore_cascade(&mut cascade);
+    select.phase = LevelSelectPhase::Finished(outcome);
+}
+
+pub fn update_campaign_text(
+    levels: Res<Levels>,
+    progress: Res<Progress>,
+    select: Res<LevelSelect>,
+    current: Res<CurrentLevel>,
+    mut overlay_query: Query<&mut Visibility, With<LevelSelectOverlay>>,
+    mut overlay_text_query: Query<&mut Text, With<LevelSelectOverlayText>>,
+    mut level_text_query: Query<&mut Text, (With<LevelText>, Without<LevelSelectOverlayText>)>,
+) {
+    let level_text = match &current.playing {
+        Some(playing) => {
+            let goal = levels.level(playing.pack, playing.level).goal;
+            let mut text = format!("Moves: {}", playing.moves);
+            if let Some(moves) = goal.moves {
+                text += &format!("/{}", moves);
+            }
+            if let Some(seconds) = goal.seconds {
+                // Counts down, rounding up so it only shows 0:00 once time is actually up
+                let left = (seconds as f32 - playing.elapsed.elapsed_secs()).max(0.0);
+                text += &format!("   Time: {}", format_time(left.ceil() as u64));
+            }
+            text + " "
+        }
+        None => String::new(),
+    };
+    let mut level_text_value = level_text_query.single_mut();
+    if level_text_value.sections[0].value != level_text {
+        level_text_value.sections[0].value = level_text;
+    }
+
+    if !select.is_changed() && !progress.is_changed() {
+        return;
+    }
+    *overlay_query.single_mut() = if select.is_open() {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    let all: Vec<_> = levels.levels().collect();
+    let overlay = match select.phase {
+        LevelSelectPhase::Off => return,
+        LevelSelectPhase::Select => level_list(&levels, &progress, &all, select.selected),
+        LevelSelectPhase::Finished(outcome) => {
+            let (_, _, level) = all[select.selected];
+            let (moves, seconds) = current.result;
+            let (heading, options) = match outcome {
+                Outcome::Won if select.selected + 1 == all.len() => (
+                    "Level complete!",
+                    "That's every level! Enter for the level list, Esc to keep playing",
+                ),
+                Outcome::Won => (
+                    "Level complete!",
+                    "Enter for the next level, Esc to keep playing",
+                ),
+                Outcome::OutOfMoves => ("Out of moves!", "Enter to try again, Esc to go back"),
+                Outcome::OutOfTime => ("Out of time!", "Enter to try again, Esc to go back"),
+            };
+            format!(
+                "{}\n\n{}\nMoves: {}   Time: {}\n\n{}",
+                heading,
+                level.name,
+                moves,
+                format_time(seconds),
+                options
+            )
+        }
+    };
+    overlay_text_query.single_mut().sections[0].value = overlay;
+}
+
+// The packs and their levels, scrolled so the selected level is always on screen, along with the
+// selected level's goal
+fn level_list(
+    levels: &Levels,
+    progress: &Progress,
+    all: &[(usize, usize, &Level)],
+    selected: usize,
+) -> String {
+    let mut lines = vec![];
+    let mut selected_line = 0;
+    for (i, &(pack, level, details)) in all.iter().enumerate() {
+        if level == 0 {
+            let pack_details = &levels.packs[pack];
+            lines.push(if progress.is_unlocked(levels, pack, 0) {
+                pack_details.name.clone()
+            } else {
+                format!(
+                    "{} (complete {} levels to unlock)",
+                    pack_details.name, pack_details.unlock_after
+                )
+            });
+        }
+        let status = match progress.best_moves(levels, pack, level) {
+            Some(moves) => format!(" - done in {} moves", moves),
+            None if !progress.is_unlocked(levels, pack, level) => " - locked".to_owned(),
+            None => String::new(),
+        };
+        let marker = if i == selected {
+            selected_line = lines.len();
+            "> "
+        } else {
+            "   "
+        };
+        lines.push(format!(
+            "{}{}. {}{}",
+            marker,
+            level + 1,
+            details.name,
+            status
+        ));
+    }
+
+    let first = selected_line
+        .saturating_sub(VISIBLE_LINES / 2)
+        .min(lines.len().saturating_sub(VISIBLE_LINES));
+    let mut text = vec!["Levels".to_owned(), String::new()];
+    text.extend(lines.into_iter().skip(first).take(VISIBLE_LINES));
+    text.push(String::new());
+    text.push(format!("Goal: {}", describe_goal(&all[selected].2.goal)));
+    text.push(String::new());
+    text.push("Up/Down to choose, Enter to play, Esc to close".to_owned());
+    text.join("\n")
+}
+
+// e.g. "make 2 clears in at most 4 moves, within 1:00"
+fn describe_goal(goal: &Goal) -> String {
+    let mut description = format!("make {} clears", goal.clears);
+    if let Some(moves) = goal.moves {
+        description += &format!(" in at most {} moves", moves);
+    }
+    if let Some(seconds) = goal.seconds {
+        description += &format!(", within {}", format_time(seconds as u64));
+    }
+    description
+}
+
+fn format_time(seconds: u64) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
@@ -0,0 +1,181 @@
+// Cascade mode turns the test kitchen into a scoring game. Whenever a row or column is made up of
+// a single kind of piece it's removed, and the gaps are filled with new pieces, which can line up
+// into more clears. Each step of a chain reaction is worth more than the one before it.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_tweening::{lens::TransformScaleLens, EaseMethod, Tween};
+use yoco_test_kitchen::board::Line;
+
+use crate::{
+    animation_in_progress,
+    controls::{Action, Actions},
+    seed::BoardRng,
+    settings::BoardSettings,
+    time_attack::TimeAttack,
+    BoardState, History, PieceQuery, FRAME_TIME, PIECE_REFILL_COMPLETED,
+};
+
+const CLEAR_DURATION: f32 = FRAME_TIME * 15.0;
+const REFILL_DURATION: f32 = FRAME_TIME * 10.0;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+enum CascadePhase {
+    // Waiting for the player to line something up
+    #[default]
+    Settled,
+    // The cleared pieces are shrinking away
+    Clearing,
+    // New pieces are growing in to fill the gaps
+    Refilling,
+}
+
+#[derive(Resource, Debug, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct Cascade {
+    pub enabled: bool,
+    pub score: u32,
+    /// How many steps the current chain reaction has gone on for
+    pub chain: u32,
+    phase: CascadePhase,
+    // The cells being cleared, as (x, y)
+    cleared: Vec<(usize, usize)>,
+}
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct ScoreText;
+
+pub fn toggle_cascade_mode(
+    actions: Actions,
+    time_attack: Res<TimeAttack>,
+    mut cascade: ResMut<Cascade>,
+) {
+    // Time attack scores clears itself, so they have to stay on the board
+    if !actions.just_pressed(Action::Cascade)
+        || cascade.phase != CascadePhase::Settled
+        || time_attack.is_active()
+    {
+        return;
+    }
+    cascade.enabled = !cascade.enabled;
+    cascade.score = 0;
+    cascade.chain = 0;
+}
+
+fn scale_tween(start: Vec3, end: Vec3, duration: f32) -> Tween<Transform> {
+    Tween::new(
+        EaseMethod::Linear,
+        Duration::from_secs_f32(duration),
+        TransformScaleLens { start, end },
+    )
+}
+
+// Steps the cascade along each time the previous step's animation finishes
+pub fn resolve_cascade(
+    mut cascade: ResMut<Cascade>,
+    mut board_state: ResMut<BoardState>,
+    mut board_rng: ResMut<BoardRng>,
+    settings: Res<BoardSettings>,
+    mut history: ResMut<History>,
+    mut piece_query: PieceQuery,
+) {
+    if !cascade.enabled || animation_in_progress(&piece_query) {
+        return;
+    }
+
+    match cascade.phase {
+        CascadePhase::Settled | CascadePhase::Refilling => {
+            let Some(board) = board_state.board() else {
+                return;
+            };
+            let lines = board.full_lines();
+            if lines.is_empty() {
+                if cascade.phase != CascadePhase::Settled {
+                    cascade.phase = CascadePhase::Settled;
+                }
+                return;
+            }
+
+            // A clear straight after the player's move starts a new chain
+            if cascade.phase == CascadePhase::Settled {
+                cascade.chain = 0;
+            }
+            cascade.chain += 1;
+            cascade.score += lines.len() as u32 * cascade.chain;
+            for line in lines {
+                let cells: Vec<(usize, usize)> = match line {
+                    Line::Row(y) => (0..board.width()).map(|x| (x, y)).collect(),
+                    Line::Col(x) => (0..board.height()).map(|y| (x, y)).collect(),
+                };
+                for cell in cells {
+                    // A row and a column cleared together share a cell
+                    if !cascade.cleared.contains(&cell) {
+                        cascade.cleared.push(cell);
+                    }
+                }
+            }
+            for &(x, y) in &cascade.cleared {
+                let (_, _, mut animator) = piece_query
+                    .get_mut(board_state.piece_state[y][x].entity)
+                    .unwrap();
+                animator.set_tweenable(scale_tween(Vec3::ONE, Vec3::ZERO, CLEAR_DURATION));
+            }
+
+            // There's no taking back a clear, and the moves before it don't apply to the new
+            // pieces
+            *history = History::default();
+            cascade.phase = CascadePhase::Clearing;
+        }
+        CascadePhase::Clearing => {
+            let cleared = std::mem::take(&mut cascade.cleared);
+            for (i, (x, y)) in cleared.into_iter().enumerate() {
+                // The board might have been resized while the pieces were being cleared
+                let Some(piece_state) = board_state
+                    .piece_state
+                    .get_mut(y)
+                    .and_then(|row| row.get_mut(x))
+                else {
+                    continue;
+                };
+                let piece = board_rng.piece(&settings.pieces);
+                piece_state.piece = Some(piece);
+
+                let (mut transform, mut sprite, mut animator) =
+                    piece_query.get_mut(piece_state.entity).unwrap();
+                sprite.index = piece.index();
+                transform.scale = Vec3::ZERO;
+                let tween = scale_tween(Vec3::ZERO, Vec3::ONE, REFILL_DURATION);
+                // The clears get counted again once the new pieces are all in
+                if i == 0 {
+                    animator.set_tweenable(tween.with_completed_event(PIECE_REFILL_COMPLETED));
+                } else {
+                    animator.set_tweenable(tween);
+                }
+            }
+            cascade.phase = CascadePhase::Refilling;
+        }
+    }
+}
+
+pub fn update_score_text(
+    cascade: Res<Cascade>,
+    mut query: Query<(&mut Text, &mut Visibility), With<ScoreText>>,
+) {
+    if !cascade.is_changed() {
+        return;
+    }
+    let (mut text, mut visibility) = query.single_mut();
+    *visibility = if cascade.enabled {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    text.sections[1].value = if cascade.chain > 1 {
+        format!("{} (chain x{})", cascade.score, cascade.chain)
+    } else {
+        cascade.score.to_string()
+    };
+}
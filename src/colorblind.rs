@@ -0,0 +1,81 @@
+// Colorblind mode draws each piece's glyph from the piece definitions over it, so pieces can be
+// told apart without relying on their colors.
+
+use bevy::prelude::*;
+use yoco_test_kitchen::board::Piece;
+
+use crate::{
+    controls::{Action, Actions},
+    loading::Pieces,
+    PieceMarker, PIECE_HEIGHT, PIECE_WIDTH,
+};
+
+#[derive(Resource, Debug, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct ColorblindMode(pub bool);
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct GlyphMarker;
+
+pub fn toggle_colorblind_mode(actions: Actions, mut mode: ResMut<ColorblindMode>) {
+    if actions.just_pressed(Action::Colorblind) {
+        mode.0 = !mode.0;
+    }
+}
+
+// Every piece entity gets a glyph as a child, so the glyph slides along with it
+pub fn add_glyphs(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    query: Query<Entity, Added<PieceMarker>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn((
+                Text2dBundle {
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("FiraSans-Bold.ttf"),
+                            font_size: 24.0,
+                            color: Color::BLACK,
+                        },
+                    )
+                    .with_alignment(TextAlignment::Center),
+                    // In the bottom right corner, where it covers the least of the art
+                    transform: Transform::from_xyz(PIECE_WIDTH * 0.3, -PIECE_HEIGHT * 0.3, 0.1),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                GlyphMarker,
+            ));
+        });
+    }
+}
+
+pub fn update_glyphs(
+    mode: Res<ColorblindMode>,
+    pieces: Res<Pieces>,
+    piece_query: Query<(&TextureAtlasSprite, &Children), With<PieceMarker>>,
+    mut glyph_query: Query<(&mut Text, &mut Visibility), With<GlyphMarker>>,
+) {
+    let wanted_visibility = if mode.0 {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    for (sprite, children) in piece_query.iter() {
+        // Sprite indices are the same as piece indices
+        let glyph = pieces.get(Piece::new(sprite.index)).glyph.to_string();
+        let mut glyph_iter = glyph_query.iter_many_mut(children);
+        while let Some((mut text, mut visibility)) = glyph_iter.fetch_next() {
+            if text.sections[0].value != glyph {
+                text.sections[0].value = glyph.clone();
+            }
+            if *visibility != wanted_visibility {
+                *visibility = wanted_visibility;
+            }
+        }
+    }
+}
@@ -0,0 +1,481 @@
+// Rebindable controls. Everything the player can do from the keyboard or a gamepad button is an
+// action, and each action can be bound to any number of keys and buttons. The bindings can be
+// changed on the controls screen, and are saved to a config file on native builds and to the
+// browser's local storage on the web.
+//
+// The screens' own keys, like the time attack menu's and this one's, can't be rebound, so there's
+// always a way back out of them.
+
+use std::collections::BTreeMap;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    campaign::LevelSelect, gamepad::GamepadInput, replay::Replay, storage, time_attack::TimeAttack,
+    Direction,
+};
+
+// How many actions the controls screen lists at once
+const VISIBLE_ACTIONS: usize = 12;
+
+const STORAGE_NAME: &str = "controls";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    RotateUp,
+    RotateDown,
+    RotateLeft,
+    RotateRight,
+    Reset,
+    Undo,
+    Redo,
+    CopyPuzzle,
+    PastePuzzle,
+    Hint,
+    Difficulty,
+    Narrower,
+    Wider,
+    Shorter,
+    Taller,
+    PieceCount,
+    Colorblind,
+    Cascade,
+    TimeAttack,
+    DailyPuzzle,
+    LevelSelect,
+}
+
+impl Action {
+    pub const ALL: [Action; 25] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::RotateUp,
+        Action::RotateDown,
+        Action::RotateLeft,
+        Action::RotateRight,
+        Action::Reset,
+        Action::Undo,
+        Action::Redo,
+        Action::CopyPuzzle,
+        Action::PastePuzzle,
+        Action::Hint,
+        Action::Difficulty,
+        Action::Narrower,
+        Action::Wider,
+        Action::Shorter,
+        Action::Taller,
+        Action::PieceCount,
+        Action::Colorblind,
+        Action::Cascade,
+        Action::TimeAttack,
+        Action::DailyPuzzle,
+        Action::LevelSelect,
+    ];
+
+    // Whether the action only plays on the board that's there, by moving the cursor or rotating a
+    // line, or doesn't touch the board at all
+    fn plays_on_board(self) -> bool {
+        matches!(
+            self,
+            Action::MoveUp
+                | Action::MoveDown
+                | Action::MoveLeft
+                | Action::MoveRight
+                | Action::RotateUp
+                | Action::RotateDown
+                | Action::RotateLeft
+                | Action::RotateRight
+                | Action::CopyPuzzle
+                | Action::Difficulty
+                | Action::Colorblind
+        )
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move cursor up",
+            Action::MoveDown => "Move cursor down",
+            Action::MoveLeft => "Move cursor left",
+            Action::MoveRight => "Move cursor right",
+            Action::RotateUp => "Rotate column up",
+            Action::RotateDown => "Rotate column down",
+            Action::RotateLeft => "Rotate row left",
+            Action::RotateRight => "Rotate row right",
+            Action::Reset => "New board",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::CopyPuzzle => "Copy puzzle code",
+            Action::PastePuzzle => "Load puzzle code",
+            Action::Hint => "Hint",
+            Action::Difficulty => "Show difficulty",
+            Action::Narrower => "Narrower board",
+            Action::Wider => "Wider board",
+            Action::Shorter => "Shorter board",
+            Action::Taller => "Taller board",
+            Action::PieceCount => "Change piece count",
+            Action::Colorblind => "Colorblind mode",
+            Action::Cascade => "Cascade mode",
+            Action::TimeAttack => "Time attack",
+            Action::DailyPuzzle => "Daily puzzle",
+            Action::LevelSelect => "Level select",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    // A key pressed while shift is held down
+    ShiftKey(KeyCode),
+    Button(GamepadButtonType),
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::ShiftKey(key) => write!(f, "Shift+{:?}", key),
+            Binding::Button(button) => write!(f, "Gamepad {:?}", button),
+        }
+    }
+}
+
+fn default_bindings(action: Action) -> Vec<Binding> {
+    use Binding::*;
+    use KeyCode::*;
+
+    match action {
+        Action::MoveUp => vec![Key(E), Key(Up)],
+        Action::MoveDown => vec![Key(D), Key(Down)],
+        Action::MoveLeft => vec![Key(S), Key(Left)],
+        Action::MoveRight => vec![Key(F), Key(Right)],
+        Action::RotateUp => vec![ShiftKey(E), ShiftKey(Up)],
+        Action::RotateDown => vec![ShiftKey(D), ShiftKey(Down)],
+        Action::RotateLeft => vec![ShiftKey(S), ShiftKey(Left)],
+        Action::RotateRight => vec![ShiftKey(F), ShiftKey(Right)],
+        Action::Reset => vec![Key(Space), Button(GamepadButtonType::South)],
+        Action::Undo => vec![Key(Z)],
+        Action::Redo => vec![ShiftKey(Z)],
+        Action::CopyPuzzle => vec![Key(C)],
+        Action::PastePuzzle => vec![Key(V)],
+        Action::Hint => vec![Key(H)],
+        Action::Difficulty => vec![Key(I)],
+        Action::Narrower => vec![Key(LBracket)],
+        Action::Wider => vec![Key(RBracket)],
+        Action::Shorter => vec![Key(Minus)],
+        Action::Taller => vec![Key(Equals)],
+        Action::PieceCount => vec![Key(P)],
+        Action::Colorblind => vec![Key(G)],
+        Action::Cascade => vec![Key(M)],
+        Action::TimeAttack => vec![Key(T)],
+        Action::DailyPuzzle => vec![Key(Y)],
+        Action::LevelSelect => vec![Key(L)],
+    }
+}
+
+/// The bindings for every action
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Controls(BTreeMap<Action, Vec<Binding>>);
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls(
+            Action::ALL
+                .into_iter()
+                .map(|action| (action, default_bindings(action)))
+                .collect(),
+        )
+    }
+}
+
+impl Controls {
+    /// The saved controls, with the defaults for any actions they leave out
+    pub fn load() -> Self {
+        let mut controls = Controls::default();
+        let Some(saved) = storage::read(STORAGE_NAME) else {
+            return controls;
+        };
+        match ron::from_str::<Controls>(&saved) {
+            Ok(saved) => controls.0.extend(saved.0),
+            Err(e) => warn!("Ignoring saved controls, they couldn't be read: {}", e),
+        }
+        controls
+    }
+
+    fn save(&self) {
+        let saved = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .expect("controls can always be written as RON");
+        if let Err(e) = storage::write(STORAGE_NAME, &saved) {
+            warn!("Couldn't save controls: {}", e);
+        }
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map_or(&[], |bindings| bindings)
+    }
+
+    // Adds a binding to `action`. A binding only ever does one thing, so it's taken away from any
+    // other action that had it.
+    fn bind(&mut self, action: Action, binding: Binding) {
+        for bindings in self.0.values_mut() {
+            bindings.retain(|&other| other != binding);
+        }
+        self.0.entry(action).or_default().push(binding);
+    }
+}
+
+/// The screens that take over all input while they're up, from the keyboard, gamepad and pointer
+/// alike
+#[derive(SystemParam)]
+pub struct Screens<'w> {
+    controls: Res<'w, ControlsScreen>,
+    level_select: Res<'w, LevelSelect>,
+}
+
+impl Screens<'_> {
+    /// True while the controls or level select screen is up, and on the frame it closes, so
+    /// whatever closed it doesn't count for anything else too
+    pub fn input_blocked(&self) -> bool {
+        self.controls.open
+            || self.controls.is_changed()
+            || self.level_select.is_open()
+            || self.level_select.is_changed()
+    }
+}
+
+/// Reads actions from whatever they're bound to
+#[derive(SystemParam)]
+pub struct Actions<'w, 's> {
+    keys: Res<'w, Input<KeyCode>>,
+    gamepad: GamepadInput<'w, 's>,
+    controls: Res<'w, Controls>,
+    screens: Screens<'w>,
+    replay: Res<'w, Replay>,
+    time_attack: Res<'w, TimeAttack>,
+}
+
+impl Actions<'_, '_> {
+    // Nothing counts as an action while one of the screens is blocking input, or while a replay
+    // is playing
+    fn blocked(&self) -> bool {
+        self.screens.input_blocked() || self.replay.is_playing()
+    }
+
+    // Once a time attack round is under way only moves and actions that leave the board alone
+    // get through, so the board can't be swapped for an easier one, and nothing gets through
+    // while its screens are up. Recordings only hold moves, resets, undo and redo, so nothing
+    // else that changes the board can be used while one is being made.
+    fn allowed(&self, action: Action) -> bool {
+        if self.time_attack.is_active() {
+            self.time_attack.accepts_input() && action.plays_on_board()
+        } else if self.replay.is_recording() {
+            action.plays_on_board() || matches!(action, Action::Reset | Action::Undo | Action::Redo)
+        } else {
+            true
+        }
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        if self.blocked() || !self.allowed(action) {
+            return false;
+        }
+        let shift_held = shift_held(&self.keys);
+        self.controls
+            .bindings(action)
+            .iter()
+            .any(|&binding| match binding {
+                Binding::Key(key) => !shift_held && self.keys.just_pressed(key),
+                Binding::ShiftKey(key) => shift_held && self.keys.just_pressed(key),
+                Binding::Button(button) => self.gamepad.just_pressed(button),
+            })
+    }
+
+    /// The direction just pressed on the gamepad's d-pad or sticks, and whether it's a rotation.
+    /// These aren't rebindable.
+    pub fn gamepad_direction(&mut self) -> Option<(Direction, bool)> {
+        // The gamepad is always read, so it keeps track of its sticks even while blocked
+        let pressed = self.gamepad.direction_pressed();
+        pressed.filter(|_| !self.blocked())
+    }
+}
+
+fn shift_held(keys: &Input<KeyCode>) -> bool {
+    keys.pressed(KeyCode::LShift) || keys.pressed(KeyCode::RShift)
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct ControlsScreen {
+    open: bool,
+    // Index into Action::ALL
+    selected: usize,
+    // Waiting for a key or button to bind to the selected action
+    capturing: bool,
+}
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct ControlsOverlay;
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct ControlsOverlayText;
+
+pub fn setup_controls(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Controls::load());
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            ControlsOverlay,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("FiraSans-Bold.ttf"),
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                ControlsOverlayText,
+            ));
+        });
+}
+
+pub fn controls_screen(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    time_attack: Res<TimeAttack>,
+    level_select: Res<LevelSelect>,
+    mut screen: ResMut<ControlsScreen>,
+    mut controls: ResMut<Controls>,
+) {
+    if !screen.open {
+        // Time attack and the level select have screens of their own, and time attack uses Esc
+        // during a round
+        if keys.just_pressed(KeyCode::F1) && !time_attack.is_active() && !level_select.is_open() {
+            screen.open = true;
+        }
+        return;
+    }
+
+    let action = Action::ALL[screen.selected];
+    if screen.capturing {
+        if keys.just_pressed(KeyCode::Escape) {
+            screen.capturing = false;
+            return;
+        }
+        let shift_held = shift_held(&keys);
+        // Shift on its own is waited out, since it might be the start of a shifted binding
+        let key = keys
+            .get_just_pressed()
+            .find(|key| !matches!(key, KeyCode::LShift | KeyCode::RShift));
+        let binding = match key {
+            Some(&key) if shift_held => Some(Binding::ShiftKey(key)),
+            Some(&key) => Some(Binding::Key(key)),
+            None => buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Button(button.button_type)),
+        };
+        if let Some(binding) = binding {
+            controls.bind(action, binding);
+            controls.save();
+            screen.capturing = false;
+        }
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::Escape) || keys.just_pressed(KeyCode::F1) {
+        screen.open = false;
+    } else if keys.just_pressed(KeyCode::Up) {
+        screen.selected = (screen.selected + Action::ALL.len() - 1) % Action::ALL.len();
+    } else if keys.just_pressed(KeyCode::Down) {
+        screen.selected = (screen.selected + 1) % Action::ALL.len();
+    } else if keys.just_pressed(KeyCode::Return) {
+        screen.capturing = true;
+    } else if keys.just_pressed(KeyCode::Delete) || keys.just_pressed(KeyCode::Back) {
+        controls.0.insert(action, vec![]);
+        controls.save();
+    } else if keys.just_pressed(KeyCode::R) {
+        *controls = Controls::default();
+        controls.save();
+    }
+}
+
+pub fn update_controls_text(
+    screen: Res<ControlsScreen>,
+    controls: Res<Controls>,
+    mut overlay_query: Query<&mut Visibility, With<ControlsOverlay>>,
+    mut text_query: Query<&mut Text, With<ControlsOverlayText>>,
+) {
+    if !screen.is_changed() && !controls.is_changed() {
+        return;
+    }
+    *overlay_query.single_mut() = if screen.open {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    if !screen.open {
+        return;
+    }
+
+    // Scroll the list so the selected action is always on it
+    let first = screen
+        .selected
+        .saturating_sub(VISIBLE_ACTIONS / 2)
+        .min(Action::ALL.len() - VISIBLE_ACTIONS);
+    let mut lines = vec!["Controls".to_owned(), String::new()];
+    for (i, &action) in Action::ALL
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(VISIBLE_ACTIONS)
+    {
+        let bindings = controls.bindings(action);
+        let bindings = if bindings.is_empty() {
+            "-".to_owned()
+        } else {
+            bindings
+                .iter()
+                .map(|binding| binding.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let marker = if i == screen.selected { "> " } else { "" };
+        lines.push(format!("{}{}: {}", marker, action.description(), bindings));
+    }
+    lines.push(String::new());
+    lines.push(if screen.capturing {
+        format!(
+            "Press a key or gamepad button for {}, or Esc to cancel",
+            Action::ALL[screen.selected].description().to_lowercase()
+        )
+    } else {
+        "Up/Down to choose, Enter to add a key or button, Delete to clear,\n\
+         R to restore the defaults, Esc to close"
+            .to_owned()
+    });
+    text_query.single_mut().sections[0].value = lines.join("\n");
+}
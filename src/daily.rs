@@ -0,0 +1,168 @@
+// The daily puzzle. Everyone playing on the same (UTC) day gets the same board and the same
+// target, whichever build they're playing on.
+
+use std::fmt;
+
+use crate::{
+    board::{Board, Piece},
+    pieces::PieceDefinitions,
+    puzzle_code::Puzzle,
+    solver,
+};
+
+const DAILY_WIDTH: usize = 5;
+const DAILY_HEIGHT: usize = 5;
+/// The daily board uses this many kinds of piece, from the front of the piece definitions, or all
+/// of them if there aren't that many
+const DAILY_PIECES: usize = 6;
+/// How many moves the target can take to reach
+const DAILY_MOVES: usize = 3;
+const DAILY_MIN_TARGET: u8 = 2;
+
+/// SplitMix64. Its output is completely fixed by the seed and this code.
+///
+/// rand makes no promises that its generators give the same numbers from one version to the next,
+/// so anything that has to come out the same for everyone, like the daily board and the boards
+/// dealt from a shared seed, is generated with this instead.
+#[derive(Clone, Debug)]
+pub struct StableRng(u64);
+
+impl StableRng {
+    pub fn new(seed: u64) -> Self {
+        StableRng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to but not including `n`
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+/// A day on the calendar
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// The date `days` days after 1970-01-01
+    pub fn from_days_since_epoch(days: i64) -> Self {
+        // Howard Hinnant's civil_from_days, which counts in 400 year eras starting from March so
+        // that leap days fall at the end of each year
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u32;
+        let year = (year_of_era + era * 400 + i64::from(month <= 2)) as i32;
+        Date { year, month, day }
+    }
+
+    fn seed(self) -> u64 {
+        // e.g. 20240131, which keeps the seed readable when debugging
+        (self.year as i64 * 10_000 + self.month as i64 * 100 + self.day as i64) as u64
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// The puzzle for `date`. Its target is the most clears that can be reached within a few moves,
+/// and boards are drawn until that comes to at least two, so there's always something to aim
+/// for.
+pub fn daily_puzzle(date: Date, pieces: &PieceDefinitions) -> Puzzle {
+    let piece_count = pieces.len().min(DAILY_PIECES);
+    let mut rng = StableRng::new(date.seed());
+    loop {
+        let board = Board::from_fn(DAILY_WIDTH, DAILY_HEIGHT, |_, _| {
+            Piece::new(rng.below(piece_count))
+        });
+        if board.has_clear() {
+            continue;
+        }
+        let (target, _) = solver::best_within(&board, DAILY_MOVES);
+        if target >= DAILY_MIN_TARGET {
+            return Puzzle {
+                board,
+                target_clears: Some(target),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_rng_is_splitmix64() {
+        // The first outputs of the reference implementation seeded with 0
+        let mut rng = StableRng::new(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+        assert!((0..1000).all(|_| rng.below(7) < 7));
+    }
+
+    #[test]
+    fn counts_days_from_the_epoch() {
+        for (days, date) in [
+            (0, "1970-01-01"),
+            (-1, "1969-12-31"),
+            (19_753, "2024-01-31"),
+            (19_782, "2024-02-29"),
+            (20_742, "2026-10-16"),
+        ] {
+            assert_eq!(Date::from_days_since_epoch(days).to_string(), date);
+        }
+    }
+
+    #[test]
+    fn daily_boards_never_change() {
+        let pieces = PieceDefinitions::from_ron(include_str!("../assets/pieces.ron")).unwrap();
+        for (date, code) in [
+            (
+                Date {
+                    year: 2024,
+                    month: 1,
+                    day: 31,
+                },
+                "DDDGF-GMHGG-MCGCG-DGHGF-DDDFM/2",
+            ),
+            (
+                Date {
+                    year: 2026,
+                    month: 10,
+                    day: 16,
+                },
+                "HCFHM-GMMMH-DMHFM-FCHDD-CFFFF/2",
+            ),
+        ] {
+            let puzzle = daily_puzzle(date, &pieces);
+            assert_eq!(puzzle.to_code(&pieces), code);
+            assert!(!puzzle.board.has_clear());
+            let (target, _) = solver::best_within(&puzzle.board, DAILY_MOVES);
+            assert_eq!(puzzle.target_clears, Some(target));
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+use yoco_test_kitchen::daily::{daily_puzzle, Date};
+
+use crate::{
+    animation_in_progress,
+    controls::{Action, Actions},
+    loading::Pieces,
+    sharing::{show_status, LoadPuzzle, StatusText},
+    PieceQuery,
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// Days change at midnight UTC, so that everyone is on the same puzzle at the same time
+#[cfg(not(target_arch = "wasm32"))]
+fn today() -> Date {
+    let since_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    Date::from_days_since_epoch((since_epoch.as_secs() / SECONDS_PER_DAY) as i64)
+}
+
+// std's clock isn't available in the browser
+#[cfg(target_arch = "wasm32")]
+fn today() -> Date {
+    let millis_since_epoch = js_sys::Date::now();
+    Date::from_days_since_epoch((millis_since_epoch / (SECONDS_PER_DAY * 1000) as f64) as i64)
+}
+
+pub fn load_daily_puzzle(
+    actions: Actions,
+    pieces: Res<Pieces>,
+    piece_query: PieceQuery,
+    mut load_puzzles: EventWriter<LoadPuzzle>,
+    mut status_query: Query<&mut Text, With<StatusText>>,
+) {
+    if !actions.just_pressed(Action::DailyPuzzle) || animation_in_progress(&piece_query) {
+        return;
+    }
+
+    let date = today();
+    let puzzle = daily_puzzle(date, &pieces);
+    load_puzzles.send(LoadPuzzle(puzzle));
+    show_status(
+        &mut status_query,
+        &format!(
+            "Daily puzzle for {}: make {} clears in as few moves as you can",
+            date,
+            puzzle.target_clears.unwrap_or_default()
+        ),
+    );
+}
@@ -0,0 +1,132 @@
+// Gamepad controls. The d-pad or left stick moves the cursor, and holding a shoulder button turns
+// those into rotations instead, the same as holding shift on the keyboard. The right stick
+// rotates straight away. Gamepads can come and go while the game is running.
+
+use bevy::{
+    ecs::system::SystemParam,
+    input::gamepad::{GamepadConnection, GamepadConnectionEvent},
+    prelude::*,
+    utils::HashMap,
+};
+
+use crate::Direction;
+
+// A stick has to be pushed this far to count as pressed in a direction, and let back in this far
+// before it can be pressed again, so it doesn't flicker on and off around a single point
+const STICK_PRESS: f32 = 0.6;
+const STICK_RELEASE: f32 = 0.4;
+
+const DPAD: [(GamepadButtonType, Direction); 4] = [
+    (GamepadButtonType::DPadUp, Direction::Up),
+    (GamepadButtonType::DPadDown, Direction::Down),
+    (GamepadButtonType::DPadLeft, Direction::Left),
+    (GamepadButtonType::DPadRight, Direction::Right),
+];
+
+// Which way each gamepad's left and right sticks were pushed as of the last frame
+#[derive(Default)]
+pub struct StickDirections(HashMap<Gamepad, [Option<Direction>; 2]>);
+
+#[derive(SystemParam)]
+pub struct GamepadInput<'w, 's> {
+    gamepads: Res<'w, Gamepads>,
+    buttons: Res<'w, Input<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+    sticks: Local<'s, StickDirections>,
+}
+
+impl GamepadInput<'_, '_> {
+    /// True if `button_type` was just pressed on any gamepad
+    pub fn just_pressed(&self, button_type: GamepadButtonType) -> bool {
+        self.gamepads.iter().any(|gamepad| {
+            self.buttons
+                .just_pressed(GamepadButton::new(gamepad, button_type))
+        })
+    }
+
+    /// True if `button_type` is held down on any gamepad
+    pub fn pressed(&self, button_type: GamepadButtonType) -> bool {
+        self.gamepads.iter().any(|gamepad| {
+            self.buttons
+                .pressed(GamepadButton::new(gamepad, button_type))
+        })
+    }
+
+    /// The direction just pressed on any gamepad, and whether it should rotate the line under the
+    /// cursor rather than move the cursor
+    pub fn direction_pressed(&mut self) -> Option<(Direction, bool)> {
+        // Forget about the sticks on gamepads that have been unplugged
+        let gamepads = &self.gamepads;
+        self.sticks
+            .0
+            .retain(|gamepad, _| gamepads.contains(*gamepad));
+
+        let rotate = self.pressed(GamepadButtonType::LeftTrigger)
+            || self.pressed(GamepadButtonType::RightTrigger);
+        let mut pressed = DPAD
+            .into_iter()
+            .find(|&(button_type, _)| self.just_pressed(button_type))
+            .map(|(_, direction)| (direction, rotate));
+
+        // Every stick is checked, even once something's been pressed, so they all stay up to date
+        for gamepad in self.gamepads.iter() {
+            for (i, (x, y)) in [
+                (GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY),
+                (GamepadAxisType::RightStickX, GamepadAxisType::RightStickY),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let stick = Vec2::new(
+                    self.axes.get(GamepadAxis::new(gamepad, x)).unwrap_or(0.0),
+                    self.axes.get(GamepadAxis::new(gamepad, y)).unwrap_or(0.0),
+                );
+                let previous = &mut self.sticks.0.entry(gamepad).or_default()[i];
+                let direction = stick_direction(stick, *previous);
+                if direction != *previous {
+                    *previous = direction;
+                    if let (Some(direction), None) = (direction, pressed) {
+                        // The right stick always rotates
+                        pressed = Some((direction, rotate || i == 1));
+                    }
+                }
+            }
+        }
+        pressed
+    }
+}
+
+// Which way a stick is pushed, if it's pushed far enough
+fn stick_direction(stick: Vec2, previous: Option<Direction>) -> Option<Direction> {
+    let distance = stick.x.abs().max(stick.y.abs());
+    if distance < STICK_RELEASE {
+        return None;
+    }
+    if distance < STICK_PRESS {
+        return previous;
+    }
+    Some(if stick.x.abs() > stick.y.abs() {
+        if stick.x > 0.0 {
+            Direction::Right
+        } else {
+            Direction::Left
+        }
+    } else if stick.y > 0.0 {
+        Direction::Up
+    } else {
+        Direction::Down
+    })
+}
+
+pub fn log_gamepad_connections(mut reader: EventReader<GamepadConnectionEvent>) {
+    for event in reader.iter() {
+        match &event.connection {
+            GamepadConnection::Connected(info) => {
+                info!("Gamepad {} connected: {}", event.gamepad.id, info.name)
+            }
+            GamepadConnection::Disconnected => {
+                info!("Gamepad {} disconnected", event.gamepad.id)
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+// Makes puzzles of a chosen difficulty, for building graded practice sets.
+//
+// A puzzle's difficulty is how many clears its target asks for and the fewest moves that reach
+// them. Every board is run through the solver before it's handed out, so a puzzle always falls
+// within the bounds it was asked for. Random boards hardly ever need more than a couple of moves,
+// so instead a few lines are filled with a single piece each and then scrambled with random
+// moves, which gives the solver something worth finding.
+
+use std::ops::RangeInclusive;
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    board::{Board, Piece},
+    puzzle_code::Puzzle,
+    solver,
+};
+
+/// How hard a generated puzzle should be
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Difficulty {
+    /// The number of clears the target can ask for. The target is always the most clears that can
+    /// be made within the largest number of `moves`.
+    pub clears: RangeInclusive<u8>,
+    /// The number of moves reaching the target can take, at the least
+    pub moves: RangeInclusive<usize>,
+}
+
+impl Difficulty {
+    /// e.g. `Difficulty::exactly(2, 4)` for puzzles that need exactly 4 moves to reach 2 clears
+    pub fn exactly(clears: u8, moves: usize) -> Self {
+        Difficulty {
+            clears: clears..=clears,
+            moves: moves..=moves,
+        }
+    }
+}
+
+/// Makes a `width` by `height` puzzle out of `pieces` that falls within `difficulty`. The board
+/// never starts out with a clear. Gives up and returns None after `attempts` boards that don't
+/// fit, which tight bounds on a small board with few kinds of piece can take a lot of.
+pub fn generate(
+    width: usize,
+    height: usize,
+    pieces: &[Piece],
+    difficulty: &Difficulty,
+    attempts: usize,
+    rng: &mut impl Rng,
+) -> Option<Puzzle> {
+    if pieces.is_empty() || difficulty.clears.is_empty() || difficulty.moves.is_empty() {
+        return None;
+    }
+    (0..attempts).find_map(|_| {
+        let board = scrambled_board(width, height, pieces, difficulty, rng);
+        if board.has_clear() {
+            return None;
+        }
+        // Throw out boards that are too easy with a quick, shallow search before doing the full
+        // one. The target has to be more than can be made in fewer moves than the puzzle needs.
+        let fewest_moves = *difficulty.moves.start();
+        if fewest_moves > 0 {
+            let (too_easy, _) = solver::best_within(&board, fewest_moves - 1);
+            if too_easy >= *difficulty.clears.end() {
+                return None;
+            }
+        }
+        let (target, moves) = solver::best_within(&board, *difficulty.moves.end());
+        (difficulty.clears.contains(&target) && difficulty.moves.contains(&moves.len())).then_some(
+            Puzzle {
+                board,
+                target_clears: Some(target),
+            },
+        )
+    })
+}
+
+// A random board with some rows or some columns made up of a single piece each, shuffled by
+// however many moves the puzzle is meant to take
+fn scrambled_board(
+    width: usize,
+    height: usize,
+    pieces: &[Piece],
+    difficulty: &Difficulty,
+    rng: &mut impl Rng,
+) -> Board {
+    // Rows from the bottom up, as the board stores them
+    let mut grid: Vec<Vec<Piece>> = (0..height)
+        .map(|_| (0..width).map(|_| *pieces.choose(rng).unwrap()).collect())
+        .collect();
+
+    let clears = rng.gen_range(difficulty.clears.clone()) as usize;
+    let rows = rng.gen_bool(0.5);
+    let line_count = if rows { height } else { width };
+    let mut lines: Vec<usize> = (0..line_count).collect();
+    lines.shuffle(rng);
+    for &line in lines.iter().take(clears) {
+        let piece = *pieces.choose(rng).unwrap();
+        if rows {
+            grid[line].fill(piece);
+        } else {
+            for row in &mut grid {
+                row[line] = piece;
+            }
+        }
+    }
+
+    let mut board = Board::from_fn(width, height, |x, y| grid[y][x]);
+    let moves: Vec<_> = solver::all_moves(&board).collect();
+    for _ in 0..rng.gen_range(difficulty.moves.clone()) {
+        board.apply(*moves.choose(rng).unwrap());
+    }
+    board
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn check_bounds(width: usize, height: usize, piece_count: usize, difficulty: Difficulty) {
+        let pieces: Vec<Piece> = (0..piece_count).map(Piece::new).collect();
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..3 {
+            let puzzle = generate(width, height, &pieces, &difficulty, 2000, &mut rng)
+                .expect("no puzzle found");
+            let board = puzzle.board;
+            assert_eq!((board.width(), board.height()), (width, height));
+            assert!(!board.has_clear());
+            assert!(board.rows().flatten().all(|piece| pieces.contains(piece)));
+
+            let target = puzzle.target_clears.unwrap();
+            assert!(difficulty.clears.contains(&target));
+            let (best, _) = solver::best_within(&board, *difficulty.moves.end());
+            assert_eq!(best, target);
+            let moves = solver::solve(&board, target, *difficulty.moves.end())
+                .unwrap()
+                .len();
+            assert!(difficulty.moves.contains(&moves));
+        }
+    }
+
+    #[test]
+    fn puzzles_fall_within_their_bounds() {
+        check_bounds(5, 5, 6, Difficulty::exactly(2, 3));
+        check_bounds(
+            6,
+            4,
+            4,
+            Difficulty {
+                clears: 1..=2,
+                moves: 1..=2,
+            },
+        );
+    }
+
+    #[test]
+    fn gives_up_on_empty_bounds() {
+        let pieces: Vec<Piece> = (0..4).map(Piece::new).collect();
+        let mut rng = StdRng::seed_from_u64(1);
+        #[allow(clippy::reversed_empty_ranges)]
+        let difficulty = Difficulty {
+            clears: 2..=1,
+            moves: 1..=3,
+        };
+        assert_eq!(generate(5, 5, &pieces, &difficulty, 10, &mut rng), None);
+    }
+}
@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+use yoco_test_kitchen::board::Clear;
+
+use crate::BoardState;
+
+// One color for each step of the cascade, so lines that only clear because of earlier ones stand
+// out from the lines that were full to begin with. Longer cascades reuse the last color.
+const STEP_COLORS: [Color; 4] = [
+    Color::rgba(1.0, 1.0, 1.0, 0.3),
+    Color::rgba(0.2, 0.8, 1.0, 0.3),
+    Color::rgba(1.0, 0.3, 0.8, 0.3),
+    Color::rgba(1.0, 0.5, 0.0, 0.3),
+];
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct ClearHighlight;
+
+pub fn spawn_clear_highlights(commands: &mut Commands, board_state: &BoardState, clears: &[Clear]) {
+    for clear in clears {
+        let (center, size) = board_state.line_rect(clear.line);
+        let color = STEP_COLORS[clear.step.min(STEP_COLORS.len() - 1)];
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(size),
+                    ..default()
+                },
+                // Under the hint, which is about what to do next rather than what's been done
+                transform: Transform::from_translation(center.extend(0.4)),
+                ..default()
+            },
+            ClearHighlight,
+        ));
+    }
+}
+
+// Describes how many lines each step of the cascade clears, e.g. "2 + 1"
+pub fn describe_steps(clears: &[Clear]) -> String {
+    let steps = clears.iter().map(|clear| clear.step + 1).max().unwrap_or(0);
+    (0..steps)
+        .map(|step| {
+            clears
+                .iter()
+                .filter(|clear| clear.step == step)
+                .count()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
@@ -0,0 +1,162 @@
+use std::f32::consts::PI;
+
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use yoco_test_kitchen::{
+    board::{Board, Line, Move},
+    solver,
+};
+
+use crate::{
+    animation_in_progress,
+    controls::{Action, Actions},
+    sharing::{show_status, StatusText},
+    BoardLocation, BoardState, PieceQuery, PlayerCursor,
+};
+
+// How far ahead the hint looks, at most. Every extra move multiplies the search time by the
+// number of moves there are to choose from, so bigger boards look fewer moves ahead.
+const HINT_SEARCH_MOVES: usize = 3;
+// Roughly how many lists of moves the hint can afford to look through without stalling a frame.
+// Enough for three moves ahead on a 5x5 board.
+const HINT_SEARCH_BUDGET: usize = 100_000;
+
+const HINT_ARROW_SIZE: f32 = 16.0;
+
+#[derive(Resource, Debug)]
+pub struct HintAssets {
+    arrow_mesh: Handle<Mesh>,
+    arrow_material: Handle<ColorMaterial>,
+}
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct HintMarker;
+
+pub fn setup_hint(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(HintAssets {
+        arrow_mesh: meshes.add(shape::RegularPolygon::new(HINT_ARROW_SIZE, 3).into()),
+        arrow_material: materials.add(ColorMaterial::from(Color::YELLOW)),
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn show_hint(
+    mut commands: Commands,
+    actions: Actions,
+    hint_assets: Res<HintAssets>,
+    board_state: Res<BoardState>,
+    piece_query: PieceQuery,
+    mut player_query: Query<(&mut BoardLocation, &mut Transform), With<PlayerCursor>>,
+    hint_query: Query<Entity, With<HintMarker>>,
+    mut status_query: Query<&mut Text, With<StatusText>>,
+) {
+    if !actions.just_pressed(Action::Hint) || animation_in_progress(&piece_query) {
+        return;
+    }
+    let Some(board) = board_state.board() else {
+        return;
+    };
+
+    for entity in hint_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let search_moves = search_moves(&board);
+    let (_, moves) = solver::best_within(&board, search_moves);
+    let Some(&mv) = moves.first() else {
+        show_status(
+            &mut status_query,
+            &format!("No better move within {} moves", search_moves),
+        );
+        return;
+    };
+
+    // Jump the cursor onto the line so the player only has to hold shift and press the arrow
+    let (mut board_location, mut transform) = player_query.single_mut();
+    match mv.line {
+        Line::Row(y) => board_location.y = y as u8,
+        Line::Col(x) => board_location.x = x as u8,
+    }
+    let world_pos =
+        board_state.piece_location_to_world_coords(board_location.x as i8, board_location.y as i8);
+    transform.translation.x = world_pos.x;
+    transform.translation.y = world_pos.y;
+
+    spawn_hint(&mut commands, &hint_assets, &board_state, mv);
+}
+
+/// The most moves ahead the hint can look on `board` while staying within its budget. Par is
+/// worked out this far ahead too, so the hint can always show the way to par.
+pub fn search_moves(board: &Board) -> usize {
+    let branching = solver::all_moves(board).count();
+    let mut moves = 1;
+    while moves < HINT_SEARCH_MOVES && branching.pow(moves as u32 + 1) <= HINT_SEARCH_BUDGET {
+        moves += 1;
+    }
+    moves
+}
+
+fn spawn_hint(
+    commands: &mut Commands,
+    hint_assets: &HintAssets,
+    board_state: &BoardState,
+    mv: Move,
+) {
+    let width = board_state.width();
+    let height = board_state.height();
+    // Highlight the whole line
+    let (center, size) = board_state.line_rect(mv.line);
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(1.0, 1.0, 0.0, 0.25),
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform::from_translation(center.extend(0.5)),
+            ..default()
+        },
+        HintMarker,
+    ));
+
+    // One arrow per step, sitting just past the edge of the board that the pieces slide towards
+    let (edge_x, edge_y, rotation) = match (mv.line, mv.steps > 0) {
+        (Line::Row(y), true) => (width as i8, y as i8, -PI / 2.0),
+        (Line::Row(y), false) => (-1, y as i8, PI / 2.0),
+        (Line::Col(x), true) => (x as i8, height as i8, 0.0),
+        (Line::Col(x), false) => (x as i8, -1, PI),
+    };
+    let edge = board_state.piece_location_to_world_coords(edge_x, edge_y);
+    let towards = Quat::from_rotation_z(rotation) * Vec3::Y;
+    for i in 0..mv.steps.unsigned_abs() {
+        let offset = towards * HINT_ARROW_SIZE * 1.5 * i as f32;
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: hint_assets.arrow_mesh.clone().into(),
+                material: hint_assets.arrow_material.clone(),
+                transform: Transform::from_translation(edge.extend(2.0) + offset)
+                    .with_rotation(Quat::from_rotation_z(rotation)),
+                ..default()
+            },
+            HintMarker,
+        ));
+    }
+}
+
+// Any change to the board makes the current hint stale
+pub fn clear_hint(
+    mut commands: Commands,
+    board_state: Res<BoardState>,
+    hint_query: Query<Entity, With<HintMarker>>,
+) {
+    if !board_state.is_changed() {
+        return;
+    }
+    for entity in hint_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+use yoco_test_kitchen::board::{Board, Move};
+
+use crate::{
+    animate_slide, animation_in_progress,
+    controls::{Action, Actions},
+    replay::{Recorded, RecordedAction, Replayed},
+    stats::NewBoard,
+    update_piece_sprites, BoardState, PieceQuery, PIECE_REDO_COMPLETED, PIECE_UNDO_COMPLETED,
+};
+
+#[derive(Copy, Clone, Debug)]
+pub enum HistoryEntry {
+    // A rotation of a single line. Undone by rotating it back.
+    Move(Move),
+    // The whole board was replaced, e.g. by a reset. Holds the board from the other side of the
+    // change, so undoing and redoing are both just a swap with the current board.
+    Board(Board),
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct History {
+    undo: Vec<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// Records a new change. Anything that had been undone can no longer be redone.
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.undo.push(entry);
+        self.redo.clear();
+    }
+}
+
+pub fn undo_redo(
+    actions: Actions,
+    mut replayed: EventReader<Replayed>,
+    mut recorded_actions: EventWriter<Recorded>,
+    mut history: ResMut<History>,
+    mut board_state: ResMut<BoardState>,
+    mut new_boards: EventWriter<NewBoard>,
+    mut piece_query: PieceQuery,
+) {
+    let mut undo = actions.just_pressed(Action::Undo);
+    let mut redo = actions.just_pressed(Action::Redo);
+    for Replayed(action) in replayed.iter() {
+        undo |= *action == RecordedAction::Undo;
+        redo |= *action == RecordedAction::Redo;
+    }
+    if !(redo || undo) || animation_in_progress(&piece_query) {
+        return;
+    }
+    let Some(mut board) = board_state.board() else {
+        return;
+    };
+
+    let history = &mut *history;
+    let (from, to) = if redo {
+        (&mut history.redo, &mut history.undo)
+    } else {
+        (&mut history.undo, &mut history.redo)
+    };
+    let Some(entry) = from.pop() else {
+        return;
+    };
+    let recorded = if redo {
+        RecordedAction::Redo
+    } else {
+        RecordedAction::Undo
+    };
+    recorded_actions.send(Recorded(recorded));
+
+    match entry {
+        HistoryEntry::Move(mv) => {
+            // Moves are stored the way they were originally made, so only undoing reverses them
+            let (mv, completed_event) = if redo {
+                (mv, PIECE_REDO_COMPLETED)
+            } else {
+                (mv.inverse(), PIECE_UNDO_COMPLETED)
+            };
+            board.apply(mv);
+            board_state.set_board(&board);
+            animate_slide(&board_state, &mut piece_query, mv, completed_event);
+            to.push(entry);
+        }
+        HistoryEntry::Board(other) => {
+            board_state.set_board(&other);
+            update_piece_sprites(&board_state, &mut piece_query);
+            new_boards.send(NewBoard(other));
+            to.push(HistoryEntry::Board(board));
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// Moves and rotations pressed while a line is still sliding are queued up and played back in
+// order once it stops, so quick players don't lose inputs. Each one is only kept for a short
+// while, measured in real time rather than frames, so a press doesn't turn into a surprise move
+// long after the fact however fast or slow the game is running.
+
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::{options::launch_option, Direction};
+
+// How long a queued input is kept, unless the launch options say otherwise
+const DEFAULT_HOLD_MILLIS: u64 = 250;
+// The most inputs that can be waiting at once. Pressing another pushes out the oldest, which is
+// the closest to being dropped anyway.
+const MAX_QUEUED: usize = 4;
+
+#[derive(Copy, Clone, Debug)]
+pub struct QueuedInput {
+    pub direction: Direction,
+    /// Whether the input rotates the line under the cursor rather than moving the cursor
+    pub rotate: bool,
+    /// When the input was pressed, as time since startup
+    pressed_at: Duration,
+}
+
+#[derive(Resource, Debug)]
+pub struct InputQueue {
+    inputs: VecDeque<QueuedInput>,
+    /// How long an input waits to be played before it's thrown away
+    hold: Duration,
+}
+
+impl InputQueue {
+    /// A queue that holds inputs for as long as asked for by whoever launched the game, e.g.
+    /// `--input-buffer 150` for 150 milliseconds. 0 only keeps an input for the frame it was
+    /// pressed on.
+    pub fn from_launch_options() -> Self {
+        let mut hold = Duration::from_millis(DEFAULT_HOLD_MILLIS);
+        if let Some(millis) = launch_option("input-buffer") {
+            match millis.parse() {
+                Ok(millis) => hold = Duration::from_millis(millis),
+                Err(_) => warn!(
+                    "Ignoring invalid input buffer {:?}, give a number of milliseconds",
+                    millis
+                ),
+            }
+        }
+        InputQueue {
+            inputs: VecDeque::new(),
+            hold,
+        }
+    }
+
+    pub fn push(&mut self, direction: Direction, rotate: bool, now: Duration) {
+        if self.inputs.len() == MAX_QUEUED {
+            self.inputs.pop_front();
+        }
+        self.inputs.push_back(QueuedInput {
+            direction,
+            rotate,
+            pressed_at: now,
+        });
+    }
+
+    /// The oldest input that's still fresh enough to play. Anything older is thrown away.
+    pub fn pop(&mut self, now: Duration) -> Option<QueuedInput> {
+        while let Some(input) = self.inputs.pop_front() {
+            if now.saturating_sub(input.pressed_at) <= self.hold {
+                return Some(input);
+            }
+        }
+        None
+    }
+
+    pub fn clear(&mut self) {
+        self.inputs.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holding(hold_millis: u64) -> InputQueue {
+        InputQueue {
+            inputs: VecDeque::new(),
+            hold: Duration::from_millis(hold_millis),
+        }
+    }
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // Everything still waiting at `now`, in the order it comes out
+    fn drain(queue: &mut InputQueue, now: Duration) -> Vec<(Direction, bool)> {
+        std::iter::from_fn(|| queue.pop(now))
+            .map(|input| (input.direction, input.rotate))
+            .collect()
+    }
+
+    #[test]
+    fn inputs_come_out_in_order() {
+        let mut queue = holding(250);
+        queue.push(Direction::Up, false, millis(0));
+        queue.push(Direction::Left, true, millis(10));
+        queue.push(Direction::Down, false, millis(20));
+        assert_eq!(
+            drain(&mut queue, millis(100)),
+            [
+                (Direction::Up, false),
+                (Direction::Left, true),
+                (Direction::Down, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn old_inputs_expire() {
+        let mut queue = holding(250);
+        queue.push(Direction::Up, false, millis(0));
+        queue.push(Direction::Right, false, millis(100));
+        assert_eq!(drain(&mut queue, millis(300)), [(Direction::Right, false)]);
+
+        // With no hold time, an input is only good for the moment it was pressed
+        let mut queue = holding(0);
+        queue.push(Direction::Up, false, millis(50));
+        assert_eq!(drain(&mut queue, millis(51)), []);
+        queue.push(Direction::Up, false, millis(60));
+        assert_eq!(drain(&mut queue, millis(60)), [(Direction::Up, false)]);
+    }
+
+    #[test]
+    fn a_full_queue_drops_the_oldest() {
+        let mut queue = holding(250);
+        let directions = [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+            Direction::Up,
+        ];
+        for (i, &direction) in directions.iter().enumerate() {
+            queue.push(direction, i % 2 == 1, millis(i as u64));
+        }
+        assert_eq!(
+            drain(&mut queue, millis(10)),
+            [
+                (Direction::Down, true),
+                (Direction::Left, false),
+                (Direction::Right, true),
+                (Direction::Up, false)
+            ]
+        );
+    }
+}
@@ -0,0 +1,298 @@
+// Level packs: fixed boards to work through in order, each with a goal to meet. They're loaded
+// from `assets/campaign.levels.ron`:
+//
+//     (
+//         packs: [
+//             (
+//                 name: "First steps",
+//                 levels: [
+//                     (
+//                         name: "Warm up",
+//                         board: "MCDFG-HMCDF-GHMCD-FGHMC-DFGHM",
+//                         goal: (clears: 1, moves: Some(2), seconds: None),
+//                     ),
+//                     ...
+//                 ],
+//             ),
+//             (name: "Harder", unlock_after: 5, levels: [...]),
+//         ],
+//     )
+//
+// Boards are puzzle codes. A pack's levels unlock one at a time as the one before is completed,
+// and a pack with `unlock_after` stays locked until that many levels have been completed across
+// the whole campaign.
+
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::{
+    board::Board,
+    pieces::PieceDefinitions,
+    puzzle_code::{Puzzle, PuzzleCodeError},
+};
+
+/// What has to be done to complete a level
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+pub struct Goal {
+    /// How many clears the board has to be left with
+    pub clears: u8,
+    /// The most moves that can be made getting there, if there's a limit
+    #[serde(default)]
+    pub moves: Option<u32>,
+    /// How long there is to get there, if there's a limit
+    #[serde(default)]
+    pub seconds: Option<u32>,
+}
+
+impl Goal {
+    pub fn is_met(&self, board: &Board) -> bool {
+        board.count_clears() >= self.clears
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Level {
+    pub name: String,
+    pub board: Board,
+    pub goal: Goal,
+}
+
+impl Level {
+    /// The level as a puzzle, aiming for the goal's clears
+    pub fn puzzle(&self) -> Puzzle {
+        Puzzle {
+            board: self.board,
+            target_clears: Some(self.goal.clears),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LevelPack {
+    pub name: String,
+    /// How many levels have to be completed, across all packs, before this one opens up
+    pub unlock_after: usize,
+    pub levels: Vec<Level>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Campaign {
+    pub packs: Vec<LevelPack>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CampaignError {
+    Parse(String),
+    InvalidBoard {
+        level: String,
+        error: PuzzleCodeError,
+    },
+    EmptyPack(String),
+}
+
+impl fmt::Display for CampaignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CampaignError::Parse(e) => write!(f, "{}", e),
+            CampaignError::InvalidBoard { level, error } => {
+                write!(f, "level {:?} has an invalid board: {}", level, error)
+            }
+            CampaignError::EmptyPack(pack) => write!(f, "pack {:?} has no levels", pack),
+        }
+    }
+}
+
+impl std::error::Error for CampaignError {}
+
+// The campaign as it's written in the file, with the boards still as puzzle codes
+#[derive(Deserialize)]
+struct CampaignFile {
+    packs: Vec<PackFile>,
+}
+
+#[derive(Deserialize)]
+struct PackFile {
+    name: String,
+    #[serde(default)]
+    unlock_after: usize,
+    levels: Vec<LevelFile>,
+}
+
+#[derive(Deserialize)]
+struct LevelFile {
+    name: String,
+    board: String,
+    goal: Goal,
+}
+
+impl Campaign {
+    /// Parses and checks the contents of a level packs file, reading the boards with `pieces`
+    pub fn from_ron(ron: &str, pieces: &PieceDefinitions) -> Result<Self, CampaignError> {
+        let file: CampaignFile =
+            ron::from_str(ron).map_err(|e| CampaignError::Parse(e.to_string()))?;
+        let packs = file
+            .packs
+            .into_iter()
+            .map(|pack| {
+                if pack.levels.is_empty() {
+                    return Err(CampaignError::EmptyPack(pack.name));
+                }
+                let levels = pack
+                    .levels
+                    .into_iter()
+                    .map(|level| {
+                        let board = Puzzle::from_code(&level.board, pieces)
+                            .map_err(|error| CampaignError::InvalidBoard {
+                                level: level.name.clone(),
+                                error,
+                            })?
+                            .board;
+                        Ok(Level {
+                            name: level.name,
+                            board,
+                            goal: level.goal,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(LevelPack {
+                    name: pack.name,
+                    unlock_after: pack.unlock_after,
+                    levels,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Campaign { packs })
+    }
+
+    /// Every level, in order, along with the index of its pack and its index within the pack
+    pub fn levels(&self) -> impl Iterator<Item = (usize, usize, &Level)> {
+        self.packs
+            .iter()
+            .enumerate()
+            .flat_map(|(pack_index, pack)| {
+                pack.levels
+                    .iter()
+                    .enumerate()
+                    .map(move |(level_index, level)| (pack_index, level_index, level))
+            })
+    }
+
+    pub fn level(&self, pack: usize, level: usize) -> &Level {
+        &self.packs[pack].levels[level]
+    }
+
+    /// Whether a level can be played, given whether each level has been completed
+    pub fn is_unlocked(
+        &self,
+        pack: usize,
+        level: usize,
+        completed: impl Fn(usize, usize) -> bool,
+    ) -> bool {
+        let completed_count = self.levels().filter(|&(p, l, _)| completed(p, l)).count();
+        completed_count >= self.packs[pack].unlock_after
+            && (level == 0 || completed(pack, level - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver;
+
+    fn pieces() -> PieceDefinitions {
+        PieceDefinitions::from_ron(include_str!("../assets/pieces.ron")).unwrap()
+    }
+
+    const TWO_PACKS: &str = r#"(
+        packs: [
+            (
+                name: "First",
+                levels: [
+                    (name: "One", board: "DCCM-MMCC-DDCC-CDMC", goal: (clears: 1)),
+                    (name: "Two", board: "CMDC-CDCD-MMDD-DDCM", goal: (clears: 1, moves: Some(3))),
+                ],
+            ),
+            (
+                name: "Second",
+                unlock_after: 2,
+                levels: [
+                    (name: "Three", board: "CMDCCDCDMMDDDDCM", goal: (clears: 2, seconds: Some(60))),
+                ],
+            ),
+        ],
+    )"#;
+
+    #[test]
+    fn reads_packs_and_levels() {
+        let campaign = Campaign::from_ron(TWO_PACKS, &pieces()).unwrap();
+        let names: Vec<_> = campaign
+            .levels()
+            .map(|(pack, level, details)| (pack, level, details.name.as_str()))
+            .collect();
+        assert_eq!(names, [(0, 0, "One"), (0, 1, "Two"), (1, 0, "Three")]);
+        assert_eq!(campaign.packs[0].unlock_after, 0);
+        assert_eq!(campaign.packs[1].unlock_after, 2);
+        assert_eq!(
+            campaign.level(0, 1).goal,
+            Goal {
+                clears: 1,
+                moves: Some(3),
+                seconds: None,
+            }
+        );
+        assert_eq!(campaign.level(1, 0).puzzle().target_clears, Some(2));
+    }
+
+    #[test]
+    fn unlocks_levels_in_order() {
+        let campaign = Campaign::from_ron(TWO_PACKS, &pieces()).unwrap();
+        let none = |_, _| false;
+        assert!(campaign.is_unlocked(0, 0, none));
+        assert!(!campaign.is_unlocked(0, 1, none));
+        assert!(!campaign.is_unlocked(1, 0, none));
+        let first = |pack, level| (pack, level) == (0, 0);
+        assert!(campaign.is_unlocked(0, 1, first));
+        assert!(!campaign.is_unlocked(1, 0, first));
+        let both = |pack, _| pack == 0;
+        assert!(campaign.is_unlocked(1, 0, both));
+    }
+
+    #[test]
+    fn reports_bad_campaigns() {
+        let pieces = pieces();
+        assert!(matches!(
+            Campaign::from_ron("(packs: [(name: \"No levels\")])", &pieces),
+            Err(CampaignError::Parse(_))
+        ));
+        assert_eq!(
+            Campaign::from_ron("(packs: [(name: \"Empty\", levels: [])])", &pieces).unwrap_err(),
+            CampaignError::EmptyPack("Empty".to_owned())
+        );
+        let bad_board = r#"(packs: [(name: "Pack", levels: [
+            (name: "Bad", board: "MCDF-GHMC-DFGH-MCDX", goal: (clears: 1)),
+        ])])"#;
+        assert_eq!(
+            Campaign::from_ron(bad_board, &pieces).unwrap_err(),
+            CampaignError::InvalidBoard {
+                level: "Bad".to_owned(),
+                error: PuzzleCodeError::UnknownPiece('X'),
+            }
+        );
+    }
+
+    #[test]
+    fn shipped_levels_can_be_completed() {
+        let campaign =
+            Campaign::from_ron(include_str!("../assets/campaign.levels.ron"), &pieces()).unwrap();
+        for (_, _, level) in campaign.levels() {
+            assert!(!level.goal.is_met(&level.board), "{}", level.name);
+            let moves = level.goal.moves.map_or(5, |moves| moves as usize);
+            assert!(
+                solver::solve(&level.board, level.goal.clears, moves).is_some(),
+                "{}",
+                level.name
+            );
+        }
+    }
+}
@@ -0,0 +1,11 @@
+//! The headless half of the test kitchen. Nothing in here touches bevy's App, so it can be driven
+//! from tools and bots as easily as from the game itself.
+
+pub mod board;
+pub mod daily;
+pub mod generator;
+pub mod levels;
+pub mod pieces;
+pub mod puzzle_code;
+pub mod rating;
+pub mod solver;
@@ -0,0 +1,155 @@
+// Everything the game needs before the board can be set up: the piece definitions, which say what
+// the pieces are and how to cut up the sprite sheet, and the level packs, whose boards are written
+// with the pieces' letters.
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadState, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use yoco_test_kitchen::{board::Piece, levels::Campaign, pieces::PieceDefinitions};
+
+const PIECE_DEFINITIONS_PATH: &str = "pieces.ron";
+const CAMPAIGN_PATH: &str = "campaign.levels.ron";
+
+#[derive(States, Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum GameState {
+    #[default]
+    Loading,
+    Playing,
+}
+
+/// The piece definitions the game is played with
+#[derive(Resource, TypeUuid, Clone, Debug, Deref)]
+#[uuid = "0b5a3c61-64e2-4d0e-8f5c-9d4f1f8e2a17"]
+pub struct Pieces(PieceDefinitions);
+
+#[derive(Default)]
+pub struct PiecesLoader;
+
+impl AssetLoader for PiecesLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let definitions = PieceDefinitions::from_ron(std::str::from_utf8(bytes)?)?;
+            load_context.set_default_asset(LoadedAsset::new(Pieces(definitions)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+/// The level packs file, which can only be read once the piece definitions are loaded
+#[derive(TypeUuid, Clone, Debug)]
+#[uuid = "5d8e2f47-93a1-4c6b-b0e5-3f7a9c1d4e82"]
+pub struct CampaignFile(String);
+
+#[derive(Default)]
+pub struct CampaignLoader;
+
+impl AssetLoader for CampaignLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let ron = std::str::from_utf8(bytes)?.to_owned();
+            load_context.set_default_asset(LoadedAsset::new(CampaignFile(ron)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["levels.ron"]
+    }
+}
+
+/// The level packs the campaign is made of
+#[derive(Resource, Clone, Debug, Default, Deref)]
+pub struct Levels(Campaign);
+
+#[derive(Resource, Debug)]
+pub struct PiecesHandle(Handle<Pieces>);
+
+#[derive(Resource, Debug)]
+pub struct CampaignHandle(Handle<CampaignFile>);
+
+pub fn start_loading(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(PiecesHandle(asset_server.load(PIECE_DEFINITIONS_PATH)));
+    commands.insert_resource(CampaignHandle(asset_server.load(CAMPAIGN_PATH)));
+}
+
+pub fn finish_loading(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    pieces_handle: Res<PiecesHandle>,
+    pieces_assets: Res<Assets<Pieces>>,
+    campaign_handle: Res<CampaignHandle>,
+    campaign_assets: Res<Assets<CampaignFile>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(pieces) = pieces_assets.get(&pieces_handle.0) else {
+        // The asset server has already logged why
+        if asset_server.get_load_state(&pieces_handle.0) == LoadState::Failed {
+            panic!(
+                "Couldn't load the piece definitions from {}",
+                PIECE_DEFINITIONS_PATH
+            );
+        }
+        return;
+    };
+
+    // The game can be played without the campaign, so it only gets a warning if it's missing
+    let campaign = match campaign_assets.get(&campaign_handle.0) {
+        Some(CampaignFile(ron)) => Campaign::from_ron(ron, pieces).unwrap_or_else(|e| {
+            warn!("Couldn't read the levels in {}: {}", CAMPAIGN_PATH, e);
+            Campaign::default()
+        }),
+        None if asset_server.get_load_state(&campaign_handle.0) == LoadState::Failed => {
+            Campaign::default()
+        }
+        None => return,
+    };
+
+    commands.insert_resource(pieces.clone());
+    commands.insert_resource(Levels(campaign));
+    next_state.set(GameState::Playing);
+}
+
+/// Cuts the sprite sheet up so that each piece's sprite index is the same as its `Piece` index,
+/// whatever order the tiles are in.
+pub fn piece_atlas(pieces: &Pieces, asset_server: &AssetServer) -> TextureAtlas {
+    let layout = &pieces.atlas;
+    let tile_size = Vec2::new(layout.tile_width as f32, layout.tile_height as f32);
+    let mut atlas = TextureAtlas::new_empty(
+        asset_server.load(layout.path.as_str()),
+        tile_size * Vec2::new(layout.columns as f32, layout.rows as f32),
+    );
+    for piece in pieces.all() {
+        let atlas_index = pieces.get(piece).atlas_index;
+        let min = tile_size
+            * Vec2::new(
+                (atlas_index % layout.columns) as f32,
+                (atlas_index / layout.columns) as f32,
+            );
+        atlas.add_texture(Rect::from_corners(min, min + tile_size));
+    }
+    atlas
+}
+
+/// The sprite for a piece, scaled to fill its space on the board whatever size the art is
+pub fn piece_sprite(piece: Piece) -> TextureAtlasSprite {
+    TextureAtlasSprite {
+        index: piece.index(),
+        custom_size: Some(Vec2::new(crate::PIECE_WIDTH, crate::PIECE_HEIGHT)),
+        ..default()
+    }
+}
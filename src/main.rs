@@ -1,10 +1,44 @@
+mod campaign;
+mod cascade;
+mod colorblind;
+mod controls;
+mod daily_puzzle;
+mod gamepad;
+mod highlight;
+mod hint;
+mod history;
+mod input_queue;
+mod loading;
+mod options;
+mod pointer;
+mod replay;
+mod seed;
+mod settings;
+mod sharing;
+mod stats;
+mod storage;
+mod time_attack;
+
 use std::time::Duration;
 
-use bevy::{prelude::*, time::Stopwatch, window::PresentMode};
+use bevy::{
+    prelude::*,
+    window::{PresentMode, PrimaryWindow},
+};
 use bevy_tweening::{
-    lens::TransformPositionLens, Animator, EaseMethod, Tween, TweenCompleted, TweeningPlugin,
+    lens::TransformPositionLens, AnimationSystem, Animator, EaseMethod, Tween, TweenCompleted,
+    TweeningPlugin,
 };
-use rand::prelude::*;
+use controls::{Action, Actions};
+use history::{History, HistoryEntry};
+use input_queue::InputQueue;
+use loading::{piece_sprite, GameState, Pieces};
+use replay::{Recorded, RecordedAction, Replay, Replayed};
+use seed::BoardRng;
+use serde::{Deserialize, Serialize};
+use settings::{BoardSettings, NextBoard};
+use stats::NewBoard;
+use yoco_test_kitchen::board::{Board, Line, Move, Piece, MAX_BOARD_SIZE};
 
 const PIECE_WIDTH: f32 = 64.0;
 const PIECE_HEIGHT: f32 = 64.0;
@@ -14,7 +48,14 @@ const FRAME_TIME: f32 = 1.0 / FPS;
 
 const PIECE_SLICE_DURATION: f32 = FRAME_TIME * 5.0;
 
+// Moves go the shortest way round, so this is the most pieces that can wrap round in one
+const MAX_WRAPPED_PIECES: usize = MAX_BOARD_SIZE / 2;
+
 const PIECE_SLIDE_COMPLETED: u64 = 1;
+const PIECE_REFILL_COMPLETED: u64 = 2;
+// Slides that take back a move or make it again, which aren't new moves of their own
+const PIECE_UNDO_COMPLETED: u64 = 3;
+const PIECE_REDO_COMPLETED: u64 = 4;
 
 fn main() {
     App::new()
@@ -32,52 +73,92 @@ fn main() {
             ..default()
         }))
         .add_plugin(TweeningPlugin)
-        .add_startup_system(setup)
-        .add_systems((update_input, move_player_cursor, maybe_reset_board).chain())
-        .add_system(update_complete_count)
-        .add_system(randomly_fill_board)
+        .add_state::<GameState>()
+        .init_resource::<colorblind::ColorblindMode>()
+        .init_resource::<cascade::Cascade>()
+        .init_resource::<stats::RoundStats>()
+        .init_resource::<stats::ShowDifficulty>()
+        .init_resource::<time_attack::TimeAttack>()
+        .init_resource::<controls::ControlsScreen>()
+        .init_resource::<campaign::LevelSelect>()
+        .init_resource::<campaign::CurrentLevel>()
+        .init_resource::<Replay>()
+        .add_event::<NewBoard>()
+        .add_event::<sharing::LoadPuzzle>()
+        .add_event::<Replayed>()
+        .add_event::<Recorded>()
+        .add_asset::<Pieces>()
+        .init_asset_loader::<loading::PiecesLoader>()
+        .add_asset::<loading::CampaignFile>()
+        .init_asset_loader::<loading::CampaignLoader>()
+        .add_startup_system(loading::start_loading)
+        .add_startup_system(hint::setup_hint)
+        .add_startup_system(time_attack::setup_time_attack)
+        .add_startup_system(controls::setup_controls)
+        .add_startup_system(campaign::setup_campaign)
+        .add_system(loading::finish_loading.in_set(OnUpdate(GameState::Loading)))
+        .add_system(setup.in_schedule(OnEnter(GameState::Playing)))
+        .add_systems(
+            (
+                controls::controls_screen,
+                time_attack::time_attack_menu,
+                time_attack::run_time_attack,
+                campaign::level_select,
+                campaign::play_level,
+                cascade::toggle_cascade_mode,
+                cascade::resolve_cascade,
+                replay::play_back,
+                update_input,
+                move_player_cursor,
+                pointer::pointer_input,
+                maybe_reset_board,
+                history::undo_redo,
+            )
+                .chain()
+                // Animations have to have been stepped before checking whether they've finished,
+                // or input can sneak in between a slide finishing and the cascade it sets off
+                .after(AnimationSystem::AnimationUpdate)
+                .in_set(OnUpdate(GameState::Playing)),
+        )
+        .add_systems(
+            (
+                sharing::copy_paste_puzzle,
+                daily_puzzle::load_daily_puzzle,
+                sharing::load_puzzle,
+                hint::show_hint,
+                hint::clear_hint,
+                settings::change_board_size,
+                settings::change_piece_count,
+                settings::apply_board_settings,
+                replay::record_actions,
+            )
+                .chain()
+                .after(history::undo_redo)
+                .in_set(OnUpdate(GameState::Playing)),
+        )
+        .add_systems(
+            (
+                update_complete_count,
+                settings::update_piece_count_text,
+                randomly_fill_board,
+                seed::update_seed_text,
+                fit_camera_to_board,
+                colorblind::toggle_colorblind_mode,
+                colorblind::add_glyphs,
+                colorblind::update_glyphs,
+                cascade::update_score_text,
+                stats::toggle_difficulty,
+                stats::update_round_stats,
+                time_attack::update_time_attack_text,
+                gamepad::log_gamepad_connections,
+                controls::update_controls_text,
+                campaign::update_campaign_text,
+            )
+                .in_set(OnUpdate(GameState::Playing)),
+        )
         .run();
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
-#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
-enum Piece {
-    Mascot,
-    Checkered,
-    Donut,
-    Flower,
-    Green,
-    Heart,
-}
-
-impl Piece {
-    fn all_pieces() -> &'static [Piece] {
-        &[
-            Piece::Mascot,
-            Piece::Checkered,
-            Piece::Donut,
-            Piece::Flower,
-            Piece::Green,
-            Piece::Heart,
-        ]
-    }
-
-    fn texture_index(self) -> usize {
-        match self {
-            Piece::Mascot => 0,
-            Piece::Checkered => 1,
-            Piece::Donut => 2,
-            Piece::Flower => 3,
-            Piece::Green => 4,
-            Piece::Heart => 5,
-        }
-    }
-}
-
-#[derive(Clone, Debug, Resource)]
-#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
-struct PiecesSpriteSheet(Handle<TextureAtlas>);
-
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
 struct PieceState {
@@ -85,42 +166,38 @@ struct PieceState {
     entity: Entity,
 }
 
-fn board_has_clear(board: &[[Piece; 5]; 5]) -> bool {
-    for y in 0..5 {
-        let p0 = board[y][0];
-        if board[y].iter().all(|pn| p0 == *pn) {
-            return true;
-        }
-    }
-    for x in 0..5 {
-        let p0 = board[0][x];
-        if (0..5).all(|y| p0 == board[y][x]) {
-            return true;
-        }
-    }
-    false
-}
-
 #[derive(Resource, Debug)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
 struct BoardState {
-    piece_state: [[PieceState; 5]; 5],
+    // Indexed as [y][x]
+    piece_state: Vec<Vec<PieceState>>,
     texture_atlas_handle: Handle<TextureAtlas>,
 
-    // Used for sliding pieces
-    extra_entity: Entity,
+    // Used for the pieces that wrap round from one end of a line to the other as it slides
+    extra_entities: [Entity; MAX_WRAPPED_PIECES],
 }
 
 impl BoardState {
-    fn empty(commands: &mut Commands, texture_atlas_handle: Handle<TextureAtlas>) -> Self {
-        let mut piece_state = [[PieceState {
-            piece: None,
-            entity: Entity::PLACEHOLDER,
-        }; 5]; 5];
-        for y in 0..5 {
-            for x in 0..5 {
-                let world_pos = piece_location_to_world_coords(x as i8, y as i8);
-                piece_state[y][x].entity = commands
+    fn empty(
+        commands: &mut Commands,
+        texture_atlas_handle: Handle<TextureAtlas>,
+        width: usize,
+        height: usize,
+    ) -> Self {
+        let mut piece_state = vec![
+            vec![
+                PieceState {
+                    piece: None,
+                    entity: Entity::PLACEHOLDER,
+                };
+                width
+            ];
+            height
+        ];
+        for (y, state_row) in piece_state.iter_mut().enumerate() {
+            for (x, piece_state) in state_row.iter_mut().enumerate() {
+                let world_pos = piece_location_to_world_coords(width, height, x as i8, y as i8);
+                piece_state.entity = commands
                     .spawn((
                         BoardLocation {
                             x: x as u8,
@@ -128,7 +205,7 @@ impl BoardState {
                         },
                         SpriteSheetBundle {
                             texture_atlas: texture_atlas_handle.clone(),
-                            sprite: TextureAtlasSprite::new(0),
+                            sprite: piece_sprite(Piece::default()),
                             transform: Transform::from_xyz(world_pos.x, world_pos.y, 0.0),
                             ..default()
                         },
@@ -140,19 +217,20 @@ impl BoardState {
                                 end: world_pos.extend(0.0),
                             },
                         )),
+                        PieceMarker,
                     ))
                     .id();
             }
         }
 
-        let extra_world_pos = piece_location_to_world_coords(5, 5);
-        BoardState {
-            piece_state,
-            extra_entity: commands
+        let extra_world_pos =
+            piece_location_to_world_coords(width, height, width as i8, height as i8);
+        let extra_entities = [(); MAX_WRAPPED_PIECES].map(|_| {
+            commands
                 .spawn((
                     SpriteSheetBundle {
                         texture_atlas: texture_atlas_handle.clone(),
-                        sprite: TextureAtlasSprite::new(0),
+                        sprite: piece_sprite(Piece::default()),
                         transform: Transform::from_xyz(extra_world_pos.x, extra_world_pos.y, 0.0),
                         ..default()
                     },
@@ -164,68 +242,141 @@ impl BoardState {
                             end: extra_world_pos.extend(0.0),
                         },
                     )),
+                    PieceMarker,
                 ))
-                .id(),
-
+                .id()
+        });
+        BoardState {
+            piece_state,
+            extra_entities,
             texture_atlas_handle,
         }
     }
 
+    fn despawn(&self, commands: &mut Commands) {
+        for piece_state in self.piece_state.iter().flatten() {
+            commands.entity(piece_state.entity).despawn_recursive();
+        }
+        for &entity in &self.extra_entities {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    // Fills in a board whose entities have only just been spawned, so their sprites can't be
+    // queried yet
+    fn fill(&mut self, commands: &mut Commands, board: &Board) {
+        self.set_board(board);
+        for piece_state in self.piece_state.iter().flatten() {
+            commands
+                .entity(piece_state.entity)
+                .insert(piece_sprite(piece_state.piece.unwrap()));
+        }
+    }
+
+    // Empties the board so that it gets filled in again
+    fn clear(&mut self) {
+        for piece_state in self.piece_state.iter_mut().flatten() {
+            piece_state.piece = None;
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.piece_state[0].len()
+    }
+
+    fn height(&self) -> usize {
+        self.piece_state.len()
+    }
+
     fn has_empty(&self) -> bool {
         self.piece_state
             .iter()
-            .flat_map(|row| row)
+            .flatten()
             .any(|ps| ps.piece.is_none())
     }
 
-    fn count_clears(&self) -> u8 {
-        let mut cnt = 0;
-        let mut ignored_rows = 0;
-        let mut ignored_cols = 0;
-
-        loop {
-            let prev_cnt = cnt;
-            for nrow in 0..5 {
-                if ignored_rows & (1 << nrow) != 0 {
-                    continue;
-                }
-                let all_eq = (0..5)
-                    .filter(|ncol| ignored_cols & (1 << ncol) == 0)
-                    .map(|ncol| self.piece_state[nrow][ncol].piece.unwrap())
-                    .all_equal();
-                if all_eq {
-                    cnt += 1;
-                    ignored_rows |= 1 << nrow;
-                }
-            }
-            for ncol in 0..5 {
-                if ignored_cols & (1 << ncol) != 0 {
-                    continue;
-                }
-                let all_eq = (0..5)
-                    .filter(|nrow| ignored_rows & (1 << nrow) == 0)
-                    .map(|nrow| self.piece_state[nrow][ncol].piece.unwrap())
-                    .all_equal();
-                if all_eq {
-                    cnt += 1;
-                    ignored_cols |= 1 << ncol;
-                }
-            }
+    /// Returns the current board, or None if it hasn't been filled in yet.
+    fn board(&self) -> Option<Board> {
+        if self.has_empty() {
+            return None;
+        }
+        Some(Board::from_fn(self.width(), self.height(), |x, y| {
+            self.piece_state[y][x].piece.unwrap()
+        }))
+    }
 
-            // We're faking a do-while here
-            if prev_cnt == cnt || ignored_rows == 0b1111 || ignored_cols == 0b1111 {
-                return cnt;
+    fn set_board(&mut self, board: &Board) {
+        for (state_row, row) in self.piece_state.iter_mut().zip(board.rows()) {
+            for (piece_state, piece) in state_row.iter_mut().zip(row) {
+                piece_state.piece = Some(*piece);
             }
         }
     }
+
+    fn piece_location_to_world_coords(&self, x: i8, y: i8) -> Vec2 {
+        piece_location_to_world_coords(self.width(), self.height(), x, y)
+    }
+
+    fn world_coords_to_piece_location(&self, pos: Vec2) -> Option<BoardLocation> {
+        world_coords_to_piece_location(self.width(), self.height(), pos)
+    }
+
+    // Where a piece `along` pieces from the start of `line` goes. Unlike
+    // piece_location_to_world_coords, this works for pieces partway between two spaces.
+    fn line_position_to_world_coords(&self, line: Line, along: f32) -> Vec2 {
+        match line {
+            Line::Row(y) => Vec2::new(
+                PIECE_WIDTH * (along - (self.width() - 1) as f32 / 2.0),
+                self.piece_location_to_world_coords(0, y as i8).y,
+            ),
+            Line::Col(x) => Vec2::new(
+                self.piece_location_to_world_coords(x as i8, 0).x,
+                PIECE_HEIGHT * (along - (self.height() - 1) as f32 / 2.0),
+            ),
+        }
+    }
+
+    // The (x, y) of each space in `line`, from start to end
+    fn line_cells(&self, line: Line) -> Vec<(usize, usize)> {
+        match line {
+            Line::Row(y) => (0..self.width()).map(|x| (x, y)).collect(),
+            Line::Col(x) => (0..self.height()).map(|y| (x, y)).collect(),
+        }
+    }
+
+    // The center and size of a whole row or column, for drawing over it
+    fn line_rect(&self, line: Line) -> (Vec2, Vec2) {
+        match line {
+            Line::Row(y) => (
+                Vec2::new(0.0, self.piece_location_to_world_coords(0, y as i8).y),
+                Vec2::new(PIECE_WIDTH * self.width() as f32, PIECE_HEIGHT),
+            ),
+            Line::Col(x) => (
+                Vec2::new(self.piece_location_to_world_coords(x as i8, 0).x, 0.0),
+                Vec2::new(PIECE_WIDTH, PIECE_HEIGHT * self.height() as f32),
+            ),
+        }
+    }
 }
 
-fn piece_location_to_world_coords(x: i8, y: i8) -> Vec2 {
-    let x = 64.0 * (x - 2) as f32;
-    let y = 64.0 * (y - 2) as f32;
+// The board is centered on the origin
+fn piece_location_to_world_coords(width: usize, height: usize, x: i8, y: i8) -> Vec2 {
+    let x = PIECE_WIDTH * (x as f32 - (width - 1) as f32 / 2.0);
+    let y = PIECE_HEIGHT * (y as f32 - (height - 1) as f32 / 2.0);
     Vec2::new(x, y)
 }
 
+// The inverse of piece_location_to_world_coords, or None if `pos` isn't over the board
+fn world_coords_to_piece_location(width: usize, height: usize, pos: Vec2) -> Option<BoardLocation> {
+    let x = (pos.x / PIECE_WIDTH + (width - 1) as f32 / 2.0).round();
+    let y = (pos.y / PIECE_HEIGHT + (height - 1) as f32 / 2.0).round();
+    let on_board = (0.0..width as f32).contains(&x) && (0.0..height as f32).contains(&y);
+    on_board.then_some(BoardLocation {
+        x: x as u8,
+        y: y as u8,
+    })
+}
+
 #[derive(Copy, Clone, Debug, Component)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
 struct BoardLocation {
@@ -241,91 +392,58 @@ struct PlayerCursor;
 #[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
 struct PieceMarker;
 
-// We keep track of the previous input. If the last input happened too long ago, ignore it
-
-#[derive(Resource, Debug, Default)]
-#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
-struct PreviousInput {
-    elapsed: Stopwatch,
-    direction: Option<Direction>,
-    shift_held: bool,
-}
-
 fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
     clear_color: Res<ClearColor>,
+    pieces: Res<Pieces>,
 ) {
     // TODO: Force the camera to a fixed resolution?
     commands.spawn(Camera2dBundle::default());
 
-    let texture_handle = asset_server.load("sprite sheet.png");
-    let atlas = TextureAtlas::from_grid(
-        texture_handle,
-        Vec2::new(PIECE_WIDTH, PIECE_HEIGHT),
-        6,
-        1,
-        None,
-        None,
-    );
-    let atlas_handle = texture_atlases.add(atlas);
+    let atlas_handle = texture_atlases.add(loading::piece_atlas(&pieces, &asset_server));
+
+    let mut settings = BoardSettings::from_launch_options(&pieces);
+    let mut seed = seed::initial_seed();
+    let replay = Replay::from_launch_options(&mut settings, &mut seed, &pieces);
+    let board_state =
+        BoardState::empty(&mut commands, atlas_handle, settings.width, settings.height);
 
     let texture_handle = asset_server.load("cursor.png");
+    let cursor_location = BoardLocation {
+        x: (settings.width / 2) as u8,
+        y: (settings.height / 2) as u8,
+    };
+    let cursor_pos = board_state
+        .piece_location_to_world_coords(cursor_location.x as i8, cursor_location.y as i8);
     commands.spawn((
         SpriteBundle {
             texture: texture_handle,
+            transform: Transform::from_translation(cursor_pos.extend(0.0)),
             ..default()
         },
-        BoardLocation { x: 2, y: 2 },
+        cursor_location,
         PlayerCursor,
     ));
 
-    let board_state = BoardState::empty(&mut commands, atlas_handle);
+    spawn_borders(
+        &mut commands,
+        clear_color.0,
+        settings.width,
+        settings.height,
+    );
+
     commands.insert_resource(board_state);
+    commands.insert_resource(settings);
 
-    commands.insert_resource(PreviousInput::default());
+    commands.insert_resource(InputQueue::from_launch_options());
+    commands.insert_resource(replay);
+    commands.insert_resource(History::default());
+    commands.insert_resource(TargetClears::default());
+    commands.insert_resource(NextBoard::default());
 
-    // Top border
-    commands.spawn(SpriteBundle {
-        sprite: Sprite {
-            color: clear_color.0,
-            custom_size: Some(Vec2::new(PIECE_WIDTH * 7.0, PIECE_WIDTH * 3.0)),
-            ..default()
-        },
-        transform: Transform::from_xyz(0.0, PIECE_WIDTH * 4.0, 1.0),
-        ..default()
-    });
-    // Bottom border
-    commands.spawn(SpriteBundle {
-        sprite: Sprite {
-            color: clear_color.0,
-            custom_size: Some(Vec2::new(PIECE_WIDTH * 7.0, PIECE_WIDTH * 3.0)),
-            ..default()
-        },
-        transform: Transform::from_xyz(0.0, -PIECE_WIDTH * 4.0, 1.0),
-        ..default()
-    });
-    // Right border
-    commands.spawn(SpriteBundle {
-        sprite: Sprite {
-            color: clear_color.0,
-            custom_size: Some(Vec2::new(PIECE_WIDTH * 3.0, PIECE_WIDTH * 7.0)),
-            ..default()
-        },
-        transform: Transform::from_xyz(PIECE_HEIGHT * 4.0, 0.0, 1.0),
-        ..default()
-    });
-    // Left border
-    commands.spawn(SpriteBundle {
-        sprite: Sprite {
-            color: clear_color.0,
-            custom_size: Some(Vec2::new(PIECE_WIDTH * 3.0, PIECE_WIDTH * 7.0)),
-            ..default()
-        },
-        transform: Transform::from_xyz(-PIECE_HEIGHT * 4.0, 0.0, 1.0),
-        ..default()
-    });
+    let board_rng = BoardRng::new(seed);
 
     commands.spawn((
         TextBundle::from_sections([
@@ -348,13 +466,224 @@ fn setup(
         ]),
         ClearCountText,
     ));
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("FiraSans-Bold.ttf"),
+                font_size: 20.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                bottom: Val::Px(30.0),
+                left: Val::Px(0.0),
+                ..default()
+            },
+            ..default()
+        }),
+        sharing::StatusText,
+    ));
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("FiraSans-Bold.ttf"),
+                font_size: 20.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(35.0),
+                left: Val::Px(0.0),
+                ..default()
+            },
+            ..default()
+        }),
+        stats::StatsText,
+    ));
+
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::new(
+                " Pieces: ",
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+            ),
+            TextSection::new(
+                "",
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+            ),
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(60.0),
+                left: Val::Px(0.0),
+                ..default()
+            },
+            ..default()
+        }),
+        settings::PieceCountText,
+    ));
+
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::new(
+                " Score: ",
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            ),
+            TextSection::new(
+                "0",
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            ),
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(85.0),
+                left: Val::Px(0.0),
+                ..default()
+            },
+            ..default()
+        }),
+        cascade::ScoreText,
+    ));
+
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::new(
+                " Seed: ",
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+            ),
+            TextSection::new(
+                board_rng.seed.to_string(),
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+            ),
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                bottom: Val::Px(5.0),
+                left: Val::Px(0.0),
+                ..default()
+            },
+            ..default()
+        }),
+        seed::SeedText,
+    ));
+    commands.insert_resource(board_rng);
+}
+
+// Covers up pieces as they slide off the edges of the board
+fn spawn_borders(commands: &mut Commands, color: Color, width: usize, height: usize) {
+    let half_width = PIECE_WIDTH * width as f32 / 2.0;
+    let half_height = PIECE_HEIGHT * height as f32 / 2.0;
+    let horizontal_size = Vec2::new(PIECE_WIDTH * (width + 2) as f32, PIECE_HEIGHT * 3.0);
+    let vertical_size = Vec2::new(PIECE_WIDTH * 3.0, PIECE_HEIGHT * (height + 2) as f32);
+
+    for (size, pos) in [
+        // Top border
+        (
+            horizontal_size,
+            Vec2::new(0.0, half_height + PIECE_HEIGHT * 1.5),
+        ),
+        // Bottom border
+        (
+            horizontal_size,
+            Vec2::new(0.0, -half_height - PIECE_HEIGHT * 1.5),
+        ),
+        // Right border
+        (
+            vertical_size,
+            Vec2::new(half_width + PIECE_WIDTH * 1.5, 0.0),
+        ),
+        // Left border
+        (
+            vertical_size,
+            Vec2::new(-half_width - PIECE_WIDTH * 1.5, 0.0),
+        ),
+    ] {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(size),
+                    ..default()
+                },
+                transform: Transform::from_translation(pos.extend(1.0)),
+                ..default()
+            },
+            BorderMarker,
+        ));
+    }
+}
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+struct BorderMarker;
+
+// Shows the whole board at its natural size when there's room, and zooms out when there isn't
+fn fit_camera_to_board(
+    board_state: Res<BoardState>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut projection_query: Query<&mut OrthographicProjection>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    // Leave a piece's worth of space on each side for the HUD
+    let needed_width = PIECE_WIDTH * (board_state.width() + 2) as f32;
+    let needed_height = PIECE_HEIGHT * (board_state.height() + 2) as f32;
+    let scale = (needed_width / window.width())
+        .max(needed_height / window.height())
+        .max(1.0);
+
+    let mut projection = projection_query.single_mut();
+    if projection.scale != scale {
+        projection.scale = scale;
+    }
 }
 
 #[derive(Copy, Clone, Debug, Component)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
 struct ClearCountText;
 
-#[derive(Copy, Clone, Debug)]
+// The number of clears the current puzzle is aiming for, if it has one
+#[derive(Resource, Debug, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+struct TargetClears(Option<u8>);
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
 enum Direction {
     Up,
@@ -363,256 +692,352 @@ enum Direction {
     Right,
 }
 
+type PieceQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Transform,
+        &'static mut TextureAtlasSprite,
+        &'static mut Animator<Transform>,
+    ),
+    (With<PieceMarker>, Without<PlayerCursor>),
+>;
+
+fn animation_in_progress(piece_query: &PieceQuery) -> bool {
+    piece_query
+        .iter()
+        .any(|(_, _, anim)| anim.tweenable().progress() < 1.0)
+}
+
 fn move_player_cursor(
     mut player_query: Query<(&mut BoardLocation, &mut Transform), With<PlayerCursor>>,
-    mut piece_query: Query<
-        (
-            &mut Transform,
-            &mut TextureAtlasSprite,
-            &mut Animator<Transform>,
-        ),
-        Without<PlayerCursor>,
-    >,
-    mut prev_input: ResMut<PreviousInput>,
+    mut piece_query: PieceQuery,
+    mut input_queue: ResMut<InputQueue>,
+    time: Res<Time>,
     mut board_state: ResMut<BoardState>,
+    mut history: ResMut<History>,
+    mut recorded_actions: EventWriter<Recorded>,
 ) {
     // While animations are playing, don't act on input.
-    let anim_in_progress = piece_query
-        .iter()
-        .any(|(_, _, anim)| anim.tweenable().progress() < 1.0);
-    if anim_in_progress || board_state.has_empty() {
+    if animation_in_progress(&piece_query) {
         return;
     }
+    let Some(board) = board_state.board() else {
+        return;
+    };
 
-    let prev_input = &mut *prev_input;
-    if let Some(direction) = prev_input.direction.take() {
-        if prev_input.elapsed.elapsed_secs() > FRAME_TIME * 3.0 {
+    // Cursor moves happen straight away, so play back every queued one up to the next rotation,
+    // which has to wait for its slide to finish before anything after it can happen
+    let (mut board_location, mut transform) = player_query.single_mut();
+    while let Some(input) = input_queue.pop(time.elapsed()) {
+        let recorded = if input.rotate {
+            RecordedAction::Rotate(input.direction)
+        } else {
+            RecordedAction::Move(input.direction)
+        };
+        recorded_actions.send(Recorded(recorded));
+
+        if input.rotate {
+            let x = board_location.x as usize;
+            let y = board_location.y as usize;
+            let mv = match input.direction {
+                Direction::Up => Move::new(Line::Col(x), 1),
+                Direction::Down => Move::new(Line::Col(x), -1),
+                Direction::Left => Move::new(Line::Row(y), -1),
+                Direction::Right => Move::new(Line::Row(y), 1),
+            };
+            slide_line(&mut board_state, &mut piece_query, &mut history, board, mv);
             return;
         }
 
-        let (mut board_location, mut transform) = player_query.single_mut();
-
-        if prev_input.shift_held {
-            // We need to move the pieces to their new location
-            let (indices, offset_x, offset_y) = match direction {
-                Direction::Up => {
-                    let idx = board_location.x as usize;
-                    let col = [(idx, 0), (idx, 1), (idx, 2), (idx, 3), (idx, 4)];
-                    (col, 0, 1)
-                }
-                Direction::Down => {
-                    let idx = board_location.x as usize;
-                    let col = [(idx, 0), (idx, 1), (idx, 2), (idx, 3), (idx, 4)];
-                    (col, 0, 4)
-                }
-                Direction::Left => {
-                    let idx = board_location.y as usize;
-                    let row = [(0, idx), (1, idx), (2, idx), (3, idx), (4, idx)];
-                    (row, 4, 0)
-                }
-                Direction::Right => {
-                    let idx = board_location.y as usize;
-                    let row = [(0, idx), (1, idx), (2, idx), (3, idx), (4, idx)];
-                    (row, 1, 0)
-                }
-            };
-            let mut piece_types = [
-                board_state.piece_state[indices[0].1][indices[0].0]
-                    .piece
-                    .unwrap(),
-                board_state.piece_state[indices[1].1][indices[1].0]
-                    .piece
-                    .unwrap(),
-                board_state.piece_state[indices[2].1][indices[2].0]
-                    .piece
-                    .unwrap(),
-                board_state.piece_state[indices[3].1][indices[3].0]
-                    .piece
-                    .unwrap(),
-                board_state.piece_state[indices[4].1][indices[4].0]
-                    .piece
-                    .unwrap(),
-            ];
-            piece_types.rotate_right(std::cmp::max(offset_x, offset_y));
+        let width = board_state.width() as u8;
+        let height = board_state.height() as u8;
+        match input.direction {
+            Direction::Up => board_location.y = (board_location.y + 1) % height,
+            Direction::Down => board_location.y = (board_location.y + height - 1) % height,
+            Direction::Left => board_location.x = (board_location.x + width - 1) % width,
+            Direction::Right => board_location.x = (board_location.x + 1) % width,
+        }
+        place_cursor(&board_state, &board_location, &mut transform);
+    }
+}
 
-            let offset_x = match offset_x {
-                0 => 0,
-                4 => 1,
-                _ => -1,
-            };
-            let offset_y = match offset_y {
-                0 => 0,
-                4 => 1,
-                _ => -1,
-            };
+fn place_cursor(
+    board_state: &BoardState,
+    board_location: &BoardLocation,
+    transform: &mut Transform,
+) {
+    let world_pos =
+        board_state.piece_location_to_world_coords(board_location.x as i8, board_location.y as i8);
+    transform.translation.x = world_pos.x;
+    transform.translation.y = world_pos.y;
+}
 
-            for ((x_idx, y_idx), piece_type) in indices.iter().zip(piece_types) {
-                let piece_state = &mut board_state.piece_state[*y_idx][*x_idx];
-                piece_state.piece = Some(piece_type);
-                let (mut transform, mut sprite, mut animator) =
-                    piece_query.get_mut(piece_state.entity).unwrap();
-                sprite.index = piece_type.texture_index();
-
-                let start_pos = piece_location_to_world_coords(
-                    *x_idx as i8 + offset_x,
-                    *y_idx as i8 + offset_y,
-                )
-                .extend(0.0);
-                let end_pos =
-                    piece_location_to_world_coords(*x_idx as i8, *y_idx as i8).extend(0.0);
-
-                // Start the animation for the piece moving
-                transform.translation = start_pos;
-                animator.set_tweenable(Tween::new(
-                    EaseMethod::Linear,
-                    Duration::from_secs_f32(PIECE_SLICE_DURATION),
-                    TransformPositionLens {
-                        start: start_pos,
-                        end: end_pos,
-                    },
-                ));
-            }
+// Makes a move the player asked for
+fn slide_line(
+    board_state: &mut BoardState,
+    piece_query: &mut PieceQuery,
+    history: &mut History,
+    mut board: Board,
+    mv: Move,
+) {
+    board.apply(mv);
+    board_state.set_board(&board);
+    animate_slide(board_state, piece_query, mv, PIECE_SLIDE_COMPLETED);
+    history.record(HistoryEntry::Move(mv));
+}
 
-            // Set up the extra piece entity so a piece appears to slice off the end
-            let (mut transform, mut sprite, mut animator) =
-                piece_query.get_mut(board_state.extra_entity).unwrap();
-
-            let last_index = if offset_x != 0 { offset_x } else { offset_y };
-            let last_index = if last_index == -1 { 4 } else { 0 };
-
-            // We already rotated, so we have to do this little bit of math instead of using
-            // the index directly.
-            sprite.index = piece_types[4 - last_index].texture_index();
-
-            let (x_idx, y_idx) = indices[last_index];
-            let start_pos = piece_location_to_world_coords(x_idx as i8, y_idx as i8).extend(0.0);
-            let end_pos =
-                piece_location_to_world_coords(x_idx as i8 - offset_x, y_idx as i8 - offset_y)
-                    .extend(0.0);
-            transform.translation = start_pos;
-            // TODO: Watch for this particular animation to finish so we can update the number of
-            //       clears
-            animator.set_tweenable(
-                Tween::new(
-                    EaseMethod::Linear,
-                    Duration::from_secs_f32(PIECE_SLICE_DURATION),
-                    TransformPositionLens {
-                        start: start_pos,
-                        end: end_pos,
-                    },
-                )
-                .with_completed_event(PIECE_SLIDE_COMPLETED),
-            );
+fn update_piece_sprites(board_state: &BoardState, piece_query: &mut PieceQuery) {
+    for piece_state in board_state.piece_state.iter().flatten() {
+        let (_, mut sprite, _) = piece_query.get_mut(piece_state.entity).unwrap();
+        sprite.index = piece_state.piece.unwrap().index();
+    }
+}
 
-            return;
-        }
+fn slide_tween(start: Vec3, end: Vec3) -> Tween<Transform> {
+    Tween::new(
+        EaseMethod::Linear,
+        Duration::from_secs_f32(PIECE_SLICE_DURATION),
+        TransformPositionLens { start, end },
+    )
+}
+
+// Animates a line having just been rotated by `mv`. BoardState must already hold the rotated
+// pieces. `completed_event` is sent once the slide finishes.
+fn animate_slide(
+    board_state: &BoardState,
+    piece_query: &mut PieceQuery,
+    mv: Move,
+    completed_event: u64,
+) {
+    let (dx, dy): (i8, i8) = match mv.line {
+        Line::Row(_) => (1, 0),
+        Line::Col(_) => (0, 1),
+    };
+    let cells = board_state.line_cells(mv.line);
+    let steps = mv.steps as i8;
+
+    for &(x, y) in &cells {
+        let piece_state = board_state.piece_state[y][x];
+        let (mut transform, mut sprite, mut animator) =
+            piece_query.get_mut(piece_state.entity).unwrap();
+        sprite.index = piece_state.piece.unwrap().index();
+
+        let start_pos = board_state
+            .piece_location_to_world_coords(x as i8 - dx * steps, y as i8 - dy * steps)
+            .extend(0.0);
+        let end_pos = board_state
+            .piece_location_to_world_coords(x as i8, y as i8)
+            .extend(0.0);
+
+        // Start the animation for the piece moving
+        transform.translation = start_pos;
+        animator.set_tweenable(slide_tween(start_pos, end_pos));
+    }
+
+    // Set up the extra piece entities so the pieces appear to slice off the end. The pieces that
+    // wrapped around are now at the front of the line, and their copies leave from where they
+    // used to be at the back.
+    let len = cells.len() as i8;
+    let wrapped_count = steps.unsigned_abs() as usize;
+    for (i, &entity) in board_state
+        .extra_entities
+        .iter()
+        .take(wrapped_count)
+        .enumerate()
+    {
+        let wrapped = if steps > 0 {
+            i as i8
+        } else {
+            len - 1 - i as i8
+        };
+        let old_index = (wrapped - steps).rem_euclid(len);
+        let (wrapped_x, wrapped_y) = cells[wrapped as usize];
+        let (old_x, old_y) = cells[old_index as usize];
+
+        let (mut transform, mut sprite, mut animator) = piece_query.get_mut(entity).unwrap();
+        sprite.index = board_state.piece_state[wrapped_y][wrapped_x]
+            .piece
+            .unwrap()
+            .index();
 
-        match direction {
-            Direction::Up => board_location.y = (board_location.y + 1) % 5,
-            Direction::Down => board_location.y = (board_location.y + 4) % 5,
-            Direction::Left => board_location.x = (board_location.x + 4) % 5,
-            Direction::Right => board_location.x = (board_location.x + 1) % 5,
+        let start_pos = board_state
+            .piece_location_to_world_coords(old_x as i8, old_y as i8)
+            .extend(0.0);
+        let end_pos = board_state
+            .piece_location_to_world_coords(old_x as i8 + dx * steps, old_y as i8 + dy * steps)
+            .extend(0.0);
+        transform.translation = start_pos;
+        let tween = slide_tween(start_pos, end_pos);
+        // Only one of them reports the slide finishing
+        if i == 0 {
+            animator.set_tweenable(tween.with_completed_event(completed_event));
+        } else {
+            animator.set_tweenable(tween);
         }
-        let world_pos =
-            piece_location_to_world_coords(board_location.x as i8, board_location.y as i8);
-        transform.translation.x = world_pos.x;
-        transform.translation.y = world_pos.y;
     }
 }
 
-fn update_input(mut prev_input: ResMut<PreviousInput>, time: Res<Time>, keys: Res<Input<KeyCode>>) {
-    let direction_pressed = if keys.just_pressed(KeyCode::E) || keys.just_pressed(KeyCode::Up) {
-        Direction::Up
-    } else if keys.just_pressed(KeyCode::D) || keys.just_pressed(KeyCode::Down) {
-        Direction::Down
-    } else if keys.just_pressed(KeyCode::S) || keys.just_pressed(KeyCode::Left) {
-        Direction::Left
-    } else if keys.just_pressed(KeyCode::F) || keys.just_pressed(KeyCode::Right) {
-        Direction::Right
-    } else {
-        if prev_input.direction.is_some() {
-            prev_input.elapsed.tick(time.delta());
-        }
+/// How a finished tween changes the number of moves made on the board. Undoing a move takes it
+/// back off the count, and redoing it puts it back on.
+fn move_count_change(event: &TweenCompleted) -> i32 {
+    match event.user_data {
+        PIECE_SLIDE_COMPLETED | PIECE_REDO_COMPLETED => 1,
+        PIECE_UNDO_COMPLETED => -1,
+        _ => 0,
+    }
+}
+
+// The actions that move the cursor or rotate a line, the direction they go in, and whether they
+// rotate
+const DIRECTION_ACTIONS: [(Action, Direction, bool); 8] = [
+    (Action::MoveUp, Direction::Up, false),
+    (Action::MoveDown, Direction::Down, false),
+    (Action::MoveLeft, Direction::Left, false),
+    (Action::MoveRight, Direction::Right, false),
+    (Action::RotateUp, Direction::Up, true),
+    (Action::RotateDown, Direction::Down, true),
+    (Action::RotateLeft, Direction::Left, true),
+    (Action::RotateRight, Direction::Right, true),
+];
+
+fn update_input(
+    mut input_queue: ResMut<InputQueue>,
+    time: Res<Time>,
+    mut actions: Actions,
+    mut replayed: EventReader<Replayed>,
+    time_attack: Res<time_attack::TimeAttack>,
+) {
+    // Nothing moves once a time attack round is over, including anything pressed just before
+    if !time_attack.accepts_input() {
+        input_queue.clear();
         return;
-    };
+    }
 
-    prev_input.elapsed.reset();
-    prev_input.direction = Some(direction_pressed);
-    prev_input.shift_held = keys.pressed(KeyCode::LShift) || keys.pressed(KeyCode::RShift);
+    for (action, direction, rotate) in DIRECTION_ACTIONS {
+        if actions.just_pressed(action) {
+            input_queue.push(direction, rotate, time.elapsed());
+        }
+    }
+    if let Some((direction, rotate)) = actions.gamepad_direction() {
+        input_queue.push(direction, rotate, time.elapsed());
+    }
+    for Replayed(action) in replayed.iter() {
+        match *action {
+            RecordedAction::Move(direction) => input_queue.push(direction, false, time.elapsed()),
+            RecordedAction::Rotate(direction) => input_queue.push(direction, true, time.elapsed()),
+            _ => {}
+        }
+    }
 }
 
-fn maybe_reset_board(keys: Res<Input<KeyCode>>, mut board_state: ResMut<BoardState>) {
-    if keys.just_pressed(KeyCode::Space) {
-        for piece_state_row in board_state.piece_state.iter_mut() {
-            for piece_state in piece_state_row.iter_mut() {
-                piece_state.piece = None;
-            }
+fn maybe_reset_board(
+    actions: Actions,
+    mut replayed: EventReader<Replayed>,
+    mut recorded: EventWriter<Recorded>,
+    mut board_state: ResMut<BoardState>,
+    mut target_clears: ResMut<TargetClears>,
+    mut history: ResMut<History>,
+) {
+    let replayed_reset = replayed
+        .iter()
+        .any(|Replayed(action)| *action == RecordedAction::Reset);
+    if actions.just_pressed(Action::Reset) || replayed_reset {
+        recorded.send(Recorded(RecordedAction::Reset));
+        target_clears.0 = None;
+        if let Some(board) = board_state.board() {
+            history.record(HistoryEntry::Board(board));
         }
+        board_state.clear();
     }
 }
 
 fn update_complete_count(
+    mut commands: Commands,
     mut reader: EventReader<TweenCompleted>,
     mut query: Query<&mut Text, With<ClearCountText>>,
     board_state: Res<BoardState>,
+    target_clears: Res<TargetClears>,
+    piece_query: PieceQuery,
+    highlight_query: Query<Entity, With<highlight::ClearHighlight>>,
 ) {
-    for event in reader.iter() {
-        if event.user_data == PIECE_SLIDE_COMPLETED {
-            let mut text = query.single_mut();
-            text.sections[1].value = format!("{}", board_state.count_clears());
+    // Slides only count once they've finished, but boards that get swapped out all at once count
+    // straight away
+    let slide_completed = reader
+        .iter()
+        .any(|event| move_count_change(event) != 0 || event.user_data == PIECE_REFILL_COMPLETED);
+    let replaced = (board_state.is_changed() || target_clears.is_changed())
+        && !animation_in_progress(&piece_query);
+    let recount = slide_completed || replaced;
+
+    // The highlights go as soon as the board starts changing, and come back once it's settled
+    if board_state.is_changed() || recount {
+        for entity in highlight_query.iter() {
+            commands.entity(entity).despawn();
         }
     }
+    if !recount {
+        return;
+    }
+
+    let clears = board_state.board().map_or(vec![], |board| board.clears());
+    highlight::spawn_clear_highlights(&mut commands, &board_state, &clears);
+
+    let mut text = query.single_mut();
+    let count = match target_clears.0 {
+        Some(target) => format!("{} / {}", clears.len(), target),
+        None => format!("{}", clears.len()),
+    };
+    // Only worth breaking down when some of the clears come from the cascade
+    text.sections[1].value = if clears.iter().any(|clear| clear.step > 0) {
+        format!("{} ({})", count, highlight::describe_steps(&clears))
+    } else {
+        count
+    };
 }
 
 fn randomly_fill_board(
     mut board_state: ResMut<BoardState>,
+    settings: Res<BoardSettings>,
+    mut board_rng: ResMut<BoardRng>,
     mut query: Query<&mut TextureAtlasSprite>,
+    mut new_boards: EventWriter<NewBoard>,
 ) {
     // Only attempt to fill in empty spaces if some actually exist
     if !board_state.has_empty() {
         return;
     }
+    let whole_board = board_state
+        .piece_state
+        .iter()
+        .flatten()
+        .all(|piece_state| piece_state.piece.is_none());
 
-    let mut rng = rand::thread_rng();
-    let starting_board = board_state.piece_state.clone();
+    if whole_board {
+        board_rng.start_board();
+    }
     let filled_board = loop {
-        let mut filled_board = [[Piece::Mascot; 5]; 5];
-        for (y, row) in starting_board.iter().enumerate() {
-            for (x, piece_state) in row.iter().enumerate() {
-                filled_board[y][x] = if let Some(piece) = piece_state.piece {
-                    piece
-                } else {
-                    *Piece::all_pieces().choose(&mut rng).unwrap()
-                };
-            }
-        }
-        if !board_has_clear(&filled_board) {
+        let filled_board = Board::from_fn(board_state.width(), board_state.height(), |x, y| {
+            board_state.piece_state[y][x]
+                .piece
+                .unwrap_or_else(|| board_rng.piece(&settings.pieces))
+        });
+        if !filled_board.has_clear() {
             break filled_board;
         }
     };
-    for (state_row, board_row) in board_state.piece_state.iter_mut().zip(filled_board) {
+    for (state_row, board_row) in board_state.piece_state.iter_mut().zip(filled_board.rows()) {
         for (piece_state, piece) in state_row.iter_mut().zip(board_row) {
             if piece_state.piece.is_some() {
                 continue;
             }
 
-            piece_state.piece = Some(piece);
+            piece_state.piece = Some(*piece);
             let mut sprite = query.get_mut(piece_state.entity).unwrap();
-            sprite.index = piece.texture_index();
+            sprite.index = piece.index();
         }
     }
-}
-
-trait IteratorExt: Iterator {
-    fn all_equal(&mut self) -> bool
-    where
-        Self: Sized,
-        Self::Item: PartialEq,
-    {
-        match self.next() {
-            None => true,
-            Some(a) => self.all(|x| a == x),
-        }
+    if whole_board {
+        new_boards.send(NewBoard(filled_board));
     }
 }
-impl<I: Iterator> IteratorExt for I {}
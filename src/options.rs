@@ -0,0 +1,32 @@
+// Options given when launching the game. Native builds take them from the command line as
+// `--name value` or `--name=value`, and the web build takes them from the page's URL as
+// `?name=value`.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn launch_option(name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+        if let Some(value) = arg
+            .strip_prefix(&flag)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(value.to_owned());
+        }
+    }
+    None
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn launch_option(name: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_owned())
+}
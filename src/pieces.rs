@@ -0,0 +1,257 @@
+// Definitions of the kinds of piece and the sprite sheet they're drawn from. These are loaded from
+// `assets/pieces.ron` so that the art and the set of pieces can change without touching the code:
+//
+//     (
+//         atlas: (path: "sprite sheet.png", tile_width: 64, tile_height: 64, columns: 8, rows: 1),
+//         pieces: [
+//             (id: 'M', name: "Mascot", atlas_index: 0, glyph: '@'),
+//             ...
+//         ],
+//     )
+//
+// The order of `pieces` matters: easier games use the pieces from the front of the list, and each
+// piece's position in it is its `Piece` index.
+
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::board::Piece;
+
+/// The fewest kinds of piece a game can be played with. With any fewer, nearly every board starts
+/// out with a clear already made.
+pub const MIN_PIECE_COUNT: usize = 3;
+
+/// How the sprite sheet is divided into tiles
+#[derive(Clone, Debug, Deserialize)]
+pub struct AtlasLayout {
+    /// Path of the sprite sheet, relative to the assets directory
+    pub path: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PieceDefinition {
+    /// The letter used for the piece in puzzle codes and text grids
+    pub id: char,
+    pub name: String,
+    /// Which tile of the sprite sheet the piece is drawn with, counting across each row
+    pub atlas_index: usize,
+    /// Drawn over the piece in colorblind mode, so it can be told apart without its colors
+    pub glyph: char,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PieceDefinitions {
+    pub atlas: AtlasLayout,
+    pieces: Vec<PieceDefinition>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PieceDefinitionsError {
+    Parse(String),
+    NotEnoughPieces(usize),
+    TooManyPieces(usize),
+    InvalidId(char),
+    DuplicateId(char),
+    AtlasIndexOutOfRange { id: char, atlas_index: usize },
+}
+
+impl fmt::Display for PieceDefinitionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PieceDefinitionsError::Parse(e) => write!(f, "{}", e),
+            PieceDefinitionsError::NotEnoughPieces(found) => write!(
+                f,
+                "at least {} pieces need to be defined, found {}",
+                MIN_PIECE_COUNT, found
+            ),
+            PieceDefinitionsError::TooManyPieces(found) => {
+                write!(f, "{} pieces are defined, at most 256 are supported", found)
+            }
+            PieceDefinitionsError::InvalidId(id) => {
+                write!(
+                    f,
+                    "{:?} can't be used as a piece id, ids must be letters",
+                    id
+                )
+            }
+            PieceDefinitionsError::DuplicateId(id) => {
+                write!(f, "{:?} is used as the id of more than one piece", id)
+            }
+            PieceDefinitionsError::AtlasIndexOutOfRange { id, atlas_index } => write!(
+                f,
+                "piece {:?} uses atlas index {}, which is past the end of the sprite sheet",
+                id, atlas_index
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PieceDefinitionsError {}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PieceChoiceError {
+    UnknownPiece(char),
+    TooFewPieces(usize),
+}
+
+impl fmt::Display for PieceChoiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PieceChoiceError::UnknownPiece(c) => write!(f, "{:?} is not a piece", c),
+            PieceChoiceError::TooFewPieces(found) => write!(
+                f,
+                "at least {} kinds of piece are needed, {} were chosen",
+                MIN_PIECE_COUNT, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PieceChoiceError {}
+
+impl PieceDefinitions {
+    /// Parses and checks the contents of a piece definitions file
+    pub fn from_ron(ron: &str) -> Result<Self, PieceDefinitionsError> {
+        let mut definitions: PieceDefinitions =
+            ron::from_str(ron).map_err(|e| PieceDefinitionsError::Parse(e.to_string()))?;
+
+        let count = definitions.pieces.len();
+        // Otherwise there'd be no piece count the game could be played with
+        if count < MIN_PIECE_COUNT {
+            return Err(PieceDefinitionsError::NotEnoughPieces(count));
+        }
+        if count > u8::MAX as usize + 1 {
+            return Err(PieceDefinitionsError::TooManyPieces(count));
+        }
+        let tiles = definitions.atlas.columns * definitions.atlas.rows;
+        for i in 0..count {
+            let piece = &mut definitions.pieces[i];
+            // Puzzle codes are read without regard to case, and use other characters to separate
+            // rows, so ids are limited to letters and stored in upper case
+            if !piece.id.is_ascii_alphabetic() {
+                return Err(PieceDefinitionsError::InvalidId(piece.id));
+            }
+            piece.id = piece.id.to_ascii_uppercase();
+            if piece.atlas_index >= tiles {
+                return Err(PieceDefinitionsError::AtlasIndexOutOfRange {
+                    id: piece.id,
+                    atlas_index: piece.atlas_index,
+                });
+            }
+            let id = piece.id;
+            if definitions.pieces[..i].iter().any(|other| other.id == id) {
+                return Err(PieceDefinitionsError::DuplicateId(id));
+            }
+        }
+        Ok(definitions)
+    }
+
+    /// Number of kinds of piece
+    pub fn len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    /// Never true, there are always at least [`MIN_PIECE_COUNT`] kinds of piece
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    /// Every kind of piece, in the order they were defined
+    pub fn all(&self) -> impl Iterator<Item = Piece> {
+        (0..self.pieces.len()).map(Piece::new)
+    }
+
+    pub fn get(&self, piece: Piece) -> &PieceDefinition {
+        &self.pieces[piece.index()]
+    }
+
+    pub fn code_char(&self, piece: Piece) -> char {
+        self.get(piece).id
+    }
+
+    pub fn from_code_char(&self, c: char) -> Option<Piece> {
+        self.pieces
+            .iter()
+            .position(|piece| piece.id == c.to_ascii_uppercase())
+            .map(Piece::new)
+    }
+
+    /// The first `count` kinds of piece, or all of them if there aren't that many
+    pub fn first(&self, count: usize) -> Vec<Piece> {
+        self.all().take(count).collect()
+    }
+
+    /// The kinds of piece picked by `chosen`, which is either how many to take from the front of
+    /// the list, e.g. `3`, or their letters, e.g. `MDH`. Repeated letters only count once.
+    pub fn choose(&self, chosen: &str) -> Result<Vec<Piece>, PieceChoiceError> {
+        let chosen = match chosen.parse::<usize>() {
+            Ok(count) => self.first(count),
+            Err(_) => {
+                let mut letters_chosen = vec![];
+                for c in chosen.chars() {
+                    let piece = self
+                        .from_code_char(c)
+                        .ok_or(PieceChoiceError::UnknownPiece(c))?;
+                    if !letters_chosen.contains(&piece) {
+                        letters_chosen.push(piece);
+                    }
+                }
+                letters_chosen
+            }
+        };
+        if chosen.len() < MIN_PIECE_COUNT {
+            return Err(PieceChoiceError::TooFewPieces(chosen.len()));
+        }
+        Ok(chosen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chooses_pieces_by_count_or_letter() {
+        let pieces = PieceDefinitions::from_ron(include_str!("../assets/pieces.ron")).unwrap();
+        let indices = |chosen: &str| {
+            pieces
+                .choose(chosen)
+                .map(|chosen| chosen.into_iter().map(Piece::index).collect::<Vec<_>>())
+        };
+        assert_eq!(indices("3"), Ok(vec![0, 1, 2]));
+        assert_eq!(indices("100"), Ok((0..pieces.len()).collect()));
+        assert_eq!(indices("hmdm"), Ok(vec![5, 0, 2]));
+        assert_eq!(indices("2"), Err(PieceChoiceError::TooFewPieces(2)));
+        assert_eq!(indices("MDMD"), Err(PieceChoiceError::TooFewPieces(2)));
+        assert_eq!(indices("MDX"), Err(PieceChoiceError::UnknownPiece('X')));
+    }
+    #[test]
+    fn needs_enough_pieces_to_play_with() {
+        // The shipped definitions cut down to their first few pieces
+        let first = |count: usize| {
+            let mut seen = 0;
+            let ron: String = include_str!("../assets/pieces.ron")
+                .lines()
+                .filter(|line| {
+                    if !line.contains("(id: ") {
+                        return true;
+                    }
+                    seen += 1;
+                    seen <= count
+                })
+                .map(|line| format!("{}\n", line))
+                .collect();
+            PieceDefinitions::from_ron(&ron).map(|pieces| pieces.len())
+        };
+        assert_eq!(first(MIN_PIECE_COUNT), Ok(MIN_PIECE_COUNT));
+        assert_eq!(
+            first(MIN_PIECE_COUNT - 1),
+            Err(PieceDefinitionsError::NotEnoughPieces(MIN_PIECE_COUNT - 1))
+        );
+    }
+}
@@ -0,0 +1,398 @@
+// Mouse and touch controls. Clicking or tapping a piece moves the cursor to it. Dragging or
+// swiping a row or column slides it along under the pointer, and letting go rotates it by however
+// many pieces it was dragged.
+
+use bevy::{input::touch::Touch, prelude::*, window::PrimaryWindow};
+use bevy_tweening::AnimatorState;
+use yoco_test_kitchen::board::{Line, Move};
+
+use crate::{
+    animation_in_progress,
+    controls::Screens,
+    place_cursor,
+    replay::{Recorded, RecordedAction, Replay, Replayed},
+    slide_line, slide_tween,
+    time_attack::TimeAttack,
+    BoardLocation, BoardState, History, HistoryEntry, PieceQuery, PlayerCursor, PIECE_HEIGHT,
+    PIECE_SLIDE_COMPLETED, PIECE_WIDTH,
+};
+
+// How far the pointer has to move before a click turns into a drag
+const DRAG_THRESHOLD: f32 = PIECE_WIDTH / 4.0;
+
+// What's doing the dragging. Only one thing drags at a time, and anything else, like a second
+// finger, is ignored until it's done.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum PointerSource {
+    Mouse,
+    Touch(u64),
+}
+
+#[derive(Debug)]
+pub struct Drag {
+    source: PointerSource,
+    // Where the drag started and where the pointer has got to, in world coordinates
+    start: Vec2,
+    current: Vec2,
+    // The width and height of the board the drag started on
+    board_size: (usize, usize),
+    // The line being dragged, once the pointer has moved far enough to tell which way it's going
+    line: Option<Line>,
+}
+
+impl Drag {
+    // How many pieces along the line has been dragged
+    fn offset(&self, line: Line) -> f32 {
+        let delta = self.current - self.start;
+        match line {
+            Line::Row(_) => delta.x / PIECE_WIDTH,
+            Line::Col(_) => delta.y / PIECE_HEIGHT,
+        }
+    }
+}
+
+// What a pointer did this frame
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum PointerState {
+    Held,
+    Released,
+    // The pointer went away without being let go, e.g. the browser took over a touch to scroll
+    Cancelled,
+}
+
+// Converts a position in the window, measured from the bottom left like the mouse cursor's, into
+// world coordinates
+fn window_to_world(camera_query: &Query<(&Camera, &GlobalTransform)>, pos: Vec2) -> Option<Vec2> {
+    let (camera, camera_transform) = camera_query.get_single().ok()?;
+    camera.viewport_to_world_2d(camera_transform, pos)
+}
+
+// Touch positions are measured from the top left of the window, unlike the mouse cursor's
+fn touch_to_world(
+    window: &Window,
+    camera_query: &Query<(&Camera, &GlobalTransform)>,
+    touch: &Touch,
+) -> Option<Vec2> {
+    let pos = touch.position();
+    window_to_world(camera_query, Vec2::new(pos.x, window.height() - pos.y))
+}
+
+// A newly pressed mouse button or finger, and where it went down
+fn new_pointer(
+    buttons: &Input<MouseButton>,
+    touches: &Touches,
+    window: &Window,
+    camera_query: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<(PointerSource, Vec2)> {
+    if buttons.just_pressed(MouseButton::Left) {
+        let pos = window_to_world(camera_query, window.cursor_position()?)?;
+        return Some((PointerSource::Mouse, pos));
+    }
+    let touch = touches.iter_just_pressed().next()?;
+    let pos = touch_to_world(window, camera_query, touch)?;
+    Some((PointerSource::Touch(touch.id()), pos))
+}
+
+// What the pointer doing the dragging did this frame, and where it is if it's still in the window
+fn follow_pointer(
+    source: PointerSource,
+    buttons: &Input<MouseButton>,
+    touches: &Touches,
+    window: &Window,
+    camera_query: &Query<(&Camera, &GlobalTransform)>,
+) -> (PointerState, Option<Vec2>) {
+    match source {
+        PointerSource::Mouse => {
+            let pos = window
+                .cursor_position()
+                .and_then(|pos| window_to_world(camera_query, pos));
+            if buttons.just_released(MouseButton::Left) {
+                (PointerState::Released, pos)
+            } else {
+                (PointerState::Held, pos)
+            }
+        }
+        PointerSource::Touch(id) => {
+            if let Some(touch) = touches.get_pressed(id) {
+                (
+                    PointerState::Held,
+                    touch_to_world(window, camera_query, touch),
+                )
+            } else if let Some(touch) = touches.get_released(id) {
+                (
+                    PointerState::Released,
+                    touch_to_world(window, camera_query, touch),
+                )
+            } else {
+                (PointerState::Cancelled, None)
+            }
+        }
+    }
+}
+
+// Rotating a line all the way round gets back to where it started, so a drag only needs to go the
+// short way round
+fn shortest_steps(steps: isize, len: usize) -> isize {
+    let len = len as isize;
+    let steps = steps.rem_euclid(len);
+    if steps > len / 2 {
+        steps - len
+    } else {
+        steps
+    }
+}
+
+// Every piece entity that moves while `line` is dragged, including the two that show the pieces
+// wrapping round the ends
+fn line_entities(board_state: &BoardState, line: Line) -> Vec<Entity> {
+    board_state
+        .line_cells(line)
+        .into_iter()
+        .map(|(x, y)| board_state.piece_state[y][x].entity)
+        .chain(board_state.extra_entities[..2].iter().copied())
+        .collect()
+}
+
+// Stops the animations on a line while it's being dragged, so they don't move the pieces out from
+// under the pointer. They're left unfinished, so nothing else touches the board until the drag is
+// over.
+fn hold_line(board_state: &BoardState, piece_query: &mut PieceQuery, line: Line) {
+    for entity in line_entities(board_state, line) {
+        let (transform, _, mut animator) = piece_query.get_mut(entity).unwrap();
+        animator.set_tweenable(slide_tween(transform.translation, transform.translation));
+        animator.state = AnimatorState::Paused;
+    }
+}
+
+// Puts the pieces in `line` `offset` pieces along from their spaces. Pieces pushed off one end
+// come back on at the other, and whichever piece is nearest each end is shown again just past
+// the other end, so the line looks unbroken as it wraps.
+fn position_line(board_state: &BoardState, piece_query: &mut PieceQuery, line: Line, offset: f32) {
+    let cells = board_state.line_cells(line);
+    let len = cells.len() as f32;
+    let mut first = (f32::MAX, 0);
+    let mut last = (f32::MIN, 0);
+    for (i, &(x, y)) in cells.iter().enumerate() {
+        let along = (i as f32 + offset + 0.5).rem_euclid(len) - 0.5;
+        if along < first.0 {
+            first = (along, i);
+        }
+        if along > last.0 {
+            last = (along, i);
+        }
+        let (mut transform, _, _) = piece_query
+            .get_mut(board_state.piece_state[y][x].entity)
+            .unwrap();
+        let pos = board_state.line_position_to_world_coords(line, along);
+        transform.translation = pos.extend(0.0);
+    }
+
+    for (entity, (along, i)) in board_state
+        .extra_entities
+        .into_iter()
+        .zip([(last.0 - len, last.1), (first.0 + len, first.1)])
+    {
+        let (x, y) = cells[i];
+        let (mut transform, mut sprite, _) = piece_query.get_mut(entity).unwrap();
+        if let Some(piece) = board_state.piece_state[y][x].piece {
+            sprite.index = piece.index();
+        }
+        let pos = board_state.line_position_to_world_coords(line, along);
+        transform.translation = pos.extend(0.0);
+    }
+}
+
+// Lets go of a dragged line, sliding its pieces the rest of the way into their spaces from
+// `offset` pieces along. BoardState must already hold the line the way it's going to end up.
+fn snap_line(
+    board_state: &BoardState,
+    piece_query: &mut PieceQuery,
+    line: Line,
+    offset: f32,
+    moved: bool,
+) {
+    let cells = board_state.line_cells(line);
+    let len = cells.len();
+    // Each entity, the space it ends up in and the space whose piece it shows. The extra entities
+    // show the pieces at each end, just past the other end.
+    let mut targets: Vec<(Entity, f32, (usize, usize))> = cells
+        .iter()
+        .enumerate()
+        .map(|(i, &(x, y))| (board_state.piece_state[y][x].entity, i as f32, (x, y)))
+        .collect();
+    targets.push((board_state.extra_entities[0], -1.0, cells[len - 1]));
+    targets.push((board_state.extra_entities[1], len as f32, cells[0]));
+
+    for (i, (entity, along, (x, y))) in targets.into_iter().enumerate() {
+        let (mut transform, mut sprite, mut animator) = piece_query.get_mut(entity).unwrap();
+        if let Some(piece) = board_state.piece_state[y][x].piece {
+            sprite.index = piece.index();
+        }
+        let start = board_state
+            .line_position_to_world_coords(line, along + offset)
+            .extend(0.0);
+        let end = board_state
+            .line_position_to_world_coords(line, along)
+            .extend(0.0);
+        transform.translation = start;
+        let tween = slide_tween(start, end);
+        // Only a drag that rotated the line counts as a move
+        if moved && i == 0 {
+            animator.set_tweenable(tween.with_completed_event(PIECE_SLIDE_COMPLETED));
+        } else {
+            animator.set_tweenable(tween);
+        }
+        animator.state = AnimatorState::Playing;
+    }
+}
+
+// Puts a dragged line back where it was without making a move
+fn cancel_drag(drag: Drag, board_state: &BoardState, piece_query: &mut PieceQuery) {
+    // A resized board has been rebuilt, so there's nothing left to put back
+    let resized = (board_state.width(), board_state.height()) != drag.board_size;
+    if let Some(line) = drag.line.filter(|_| !resized) {
+        snap_line(board_state, piece_query, line, drag.offset(line), false);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn pointer_input(
+    buttons: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    time_attack: Res<TimeAttack>,
+    screens: Screens,
+    replay: Res<Replay>,
+    mut replayed: EventReader<Replayed>,
+    mut recorded: EventWriter<Recorded>,
+    mut drag: Local<Option<Drag>>,
+    mut player_query: Query<(&mut BoardLocation, &mut Transform), With<PlayerCursor>>,
+    mut piece_query: PieceQuery,
+    mut board_state: ResMut<BoardState>,
+    mut history: ResMut<History>,
+) {
+    // Give up on the drag if something else has changed the board in the meantime, or one of the
+    // screens has come up over it
+    let covered = !time_attack.accepts_input() || screens.input_blocked();
+    if board_state.is_changed() || covered {
+        if let Some(drag) = drag.take() {
+            cancel_drag(drag, &board_state, &mut piece_query);
+        }
+    }
+    if covered {
+        return;
+    }
+
+    // Recorded taps and drags are played back by going straight to where they ended up, and the
+    // pointer itself is ignored until the replay's over
+    if replay.is_playing() {
+        for Replayed(action) in replayed.iter() {
+            match *action {
+                RecordedAction::PlaceCursor { x, y } => {
+                    let (mut board_location, mut transform) = player_query.single_mut();
+                    *board_location = BoardLocation { x, y };
+                    place_cursor(&board_state, &board_location, &mut transform);
+                }
+                RecordedAction::Slide(mv) => {
+                    let board = board_state.board().unwrap();
+                    slide_line(&mut board_state, &mut piece_query, &mut history, board, mv);
+                }
+                _ => {}
+            }
+        }
+        return;
+    }
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+
+    if drag.is_none() {
+        // Drags have to start on a piece, so there's a line to rotate
+        *drag = new_pointer(&buttons, &touches, window, &camera_query)
+            .filter(|&(_, pos)| board_state.world_coords_to_piece_location(pos).is_some())
+            .map(|(source, pos)| Drag {
+                source,
+                start: pos,
+                current: pos,
+                board_size: (board_state.width(), board_state.height()),
+                line: None,
+            });
+    }
+    // A quick tap can go down and come back up within a single frame, so carry straight on
+    let Some(current) = drag.as_mut() else {
+        return;
+    };
+    let (state, pos) = follow_pointer(current.source, &buttons, &touches, window, &camera_query);
+    // Keep going from the last place the pointer was seen if it leaves the window
+    if let Some(pos) = pos {
+        current.current = pos;
+    }
+    let location = board_state
+        .world_coords_to_piece_location(current.start)
+        .unwrap();
+
+    match state {
+        PointerState::Held => {}
+        PointerState::Cancelled => {
+            cancel_drag(drag.take().unwrap(), &board_state, &mut piece_query);
+            return;
+        }
+        PointerState::Released => {
+            let drag = drag.take().unwrap();
+            let (mut board_location, mut transform) = player_query.single_mut();
+            *board_location = location;
+            place_cursor(&board_state, &board_location, &mut transform);
+            recorded.send(Recorded(RecordedAction::PlaceCursor {
+                x: location.x,
+                y: location.y,
+            }));
+
+            let Some(line) = drag.line else {
+                return;
+            };
+            let offset = drag.offset(line);
+            let steps = offset.round();
+            let len = board_state.line_cells(line).len();
+            let mv = Move::new(line, shortest_steps(steps as isize, len));
+            if mv.steps != 0 {
+                let mut board = board_state.board().unwrap();
+                board.apply(mv);
+                board_state.set_board(&board);
+                history.record(HistoryEntry::Move(mv));
+                recorded.send(Recorded(RecordedAction::Slide(mv)));
+            }
+            snap_line(
+                &board_state,
+                &mut piece_query,
+                line,
+                offset - steps,
+                mv.steps != 0,
+            );
+            return;
+        }
+    }
+
+    let line = match current.line {
+        Some(line) => line,
+        None => {
+            let delta = current.current - current.start;
+            // Wait for anything that's still moving to settle before picking up the line
+            if delta.length() < DRAG_THRESHOLD
+                || animation_in_progress(&piece_query)
+                || board_state.has_empty()
+            {
+                return;
+            }
+            let line = if delta.x.abs() >= delta.y.abs() {
+                Line::Row(location.y as usize)
+            } else {
+                Line::Col(location.x as usize)
+            };
+            hold_line(&board_state, &mut piece_query, line);
+            current.line = Some(line);
+            line
+        }
+    };
+    position_line(&board_state, &mut piece_query, line, current.offset(line));
+}
@@ -0,0 +1,234 @@
+// Short text codes for sharing boards.
+//
+// A code is one letter per piece, reading each row from left to right starting with the top row.
+// Rows are separated by `-`, and the code can end with `/` and the number of clears the puzzle
+// is aiming for:
+//
+//     MCDFG-HMCDF-GHMCD-FGHMC-DFGHM/3
+//
+// Whitespace also separates rows, so a grid of letters can be pasted in as is. Square boards can
+// leave the separators out entirely. The letters are the piece ids from the piece definitions.
+
+use std::fmt;
+
+use crate::{board::Board, pieces::PieceDefinitions};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Puzzle {
+    pub board: Board,
+    pub target_clears: Option<u8>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PuzzleCodeError {
+    UnknownPiece(char),
+    NotSquare(usize),
+    RaggedRows {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    UnsupportedSize {
+        width: usize,
+        height: usize,
+    },
+    InvalidTarget(String),
+}
+
+impl fmt::Display for PuzzleCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PuzzleCodeError::UnknownPiece(c) => write!(f, "{:?} is not a piece", c),
+            PuzzleCodeError::NotSquare(found) => write!(
+                f,
+                "{} pieces don't make a square board, separate the rows with '-'",
+                found
+            ),
+            PuzzleCodeError::RaggedRows {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {} has {} pieces but the first row has {}",
+                row, found, expected
+            ),
+            PuzzleCodeError::UnsupportedSize { width, height } => {
+                write!(f, "{}x{} is not a supported board size", width, height)
+            }
+            PuzzleCodeError::InvalidTarget(target) => {
+                write!(f, "{:?} is not a number of clears", target)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PuzzleCodeError {}
+
+impl Puzzle {
+    pub fn new(board: Board) -> Self {
+        Puzzle {
+            board,
+            target_clears: None,
+        }
+    }
+
+    /// The puzzle's code, using the letters from `pieces`
+    pub fn to_code(&self, pieces: &PieceDefinitions) -> String {
+        let rows: Vec<String> = self
+            .board
+            .rows()
+            .rev()
+            .map(|row| row.iter().map(|&piece| pieces.code_char(piece)).collect())
+            .collect();
+        let mut code = rows.join("-");
+        if let Some(target) = self.target_clears {
+            code += &format!("/{}", target);
+        }
+        code
+    }
+
+    pub fn from_code(code: &str, pieces: &PieceDefinitions) -> Result<Self, PuzzleCodeError> {
+        let (pieces_code, target_code) = match code.split_once('/') {
+            Some((pieces_code, target_code)) => (pieces_code, Some(target_code.trim())),
+            None => (code, None),
+        };
+
+        // Rows from the top down, the way they're written
+        let mut rows = pieces_code
+            .split(|c: char| c.is_whitespace() || c == '-')
+            .filter(|row| !row.is_empty())
+            .map(|row| {
+                row.chars()
+                    .map(|c| {
+                        pieces
+                            .from_code_char(c)
+                            .ok_or(PuzzleCodeError::UnknownPiece(c))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if rows.len() == 1 {
+            let pieces = rows.pop().unwrap();
+            let side = (1..=pieces.len())
+                .find(|side| side * side >= pieces.len())
+                .unwrap_or(0);
+            if side * side != pieces.len() {
+                return Err(PuzzleCodeError::NotSquare(pieces.len()));
+            }
+            rows = pieces.chunks(side).map(|row| row.to_vec()).collect();
+        }
+
+        let height = rows.len();
+        let width = rows.first().map_or(0, |row| row.len());
+        if let Some((i, row)) = rows.iter().enumerate().find(|(_, row)| row.len() != width) {
+            return Err(PuzzleCodeError::RaggedRows {
+                row: i + 1,
+                expected: width,
+                found: row.len(),
+            });
+        }
+        if !Board::is_valid_size(width, height) {
+            return Err(PuzzleCodeError::UnsupportedSize { width, height });
+        }
+
+        let target_clears = target_code
+            .map(|target| {
+                target
+                    .parse()
+                    .map_err(|_| PuzzleCodeError::InvalidTarget(target.to_owned()))
+            })
+            .transpose()?;
+
+        // The code starts with the top row, but the board starts with the bottom one
+        let board = Board::from_fn(width, height, |x, y| rows[height - 1 - y][x]);
+        Ok(Puzzle {
+            board,
+            target_clears,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Piece;
+
+    fn pieces() -> PieceDefinitions {
+        PieceDefinitions::from_ron(include_str!("../assets/pieces.ron")).unwrap()
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        let pieces = pieces();
+        for (width, height, target_clears) in [(5, 5, Some(3)), (4, 7, None), (8, 6, Some(12))] {
+            let board = Board::from_fn(width, height, |x, y| Piece::new((x * 3 + y * 5) % 8));
+            let puzzle = Puzzle {
+                board,
+                target_clears,
+            };
+            let code = puzzle.to_code(&pieces);
+            assert_eq!(Puzzle::from_code(&code, &pieces), Ok(puzzle), "{}", code);
+        }
+    }
+
+    #[test]
+    fn reads_the_top_row_first() {
+        let pieces = pieces();
+        let puzzle = Puzzle::from_code("MMMM-CCCC-DDDD-FFFF/2", &pieces).unwrap();
+        assert_eq!(pieces.code_char(puzzle.board.get(0, 3)), 'M');
+        assert_eq!(pieces.code_char(puzzle.board.get(0, 0)), 'F');
+        assert_eq!(puzzle.target_clears, Some(2));
+    }
+
+    #[test]
+    fn accepts_other_ways_of_writing_rows() {
+        let pieces = pieces();
+        let expected = Puzzle::from_code("MCDF-GHMC-DFGH-MCDF", &pieces).unwrap();
+        for code in [
+            "MCDFGHMCDFGHMCDF",
+            "mcdf ghmc dfgh mcdf",
+            "MCDF\nGHMC\nDFGH\nMCDF\n",
+        ] {
+            assert_eq!(Puzzle::from_code(code, &pieces), Ok(expected), "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn reports_bad_codes() {
+        let pieces = pieces();
+        for (code, error) in [
+            ("MCDF-GHMC-DFGH-MCDX", PuzzleCodeError::UnknownPiece('X')),
+            ("MCDFGHMCDFGHMCD", PuzzleCodeError::NotSquare(15)),
+            (
+                "MCDF-GHMC-DFG-MCDF",
+                PuzzleCodeError::RaggedRows {
+                    row: 3,
+                    expected: 4,
+                    found: 3,
+                },
+            ),
+            (
+                "MCD-GHM-DFG",
+                PuzzleCodeError::UnsupportedSize {
+                    width: 3,
+                    height: 3,
+                },
+            ),
+            (
+                "",
+                PuzzleCodeError::UnsupportedSize {
+                    width: 0,
+                    height: 0,
+                },
+            ),
+            (
+                "MCDF-GHMC-DFGH-MCDF/lots",
+                PuzzleCodeError::InvalidTarget("lots".to_owned()),
+            ),
+        ] {
+            assert_eq!(Puzzle::from_code(code, &pieces), Err(error), "{:?}", code);
+        }
+    }
+}
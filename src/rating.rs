@@ -0,0 +1,119 @@
+// Rates how hard a board is to solve, going further than the fewest moves it takes.
+//
+// A board that takes three moves can still be easy if there are lots of ways to do it, or hard if
+// there's only one and plenty of moves that look like they're heading somewhere but aren't. The
+// score is roughly how many yes-or-no guesses it would take to stumble on a solution: every move
+// of the solution is a pick out of all the moves there are, made easier by every other move that
+// would have kept the target in reach, and every trap makes it harder.
+
+use crate::{board::Board, solver};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(
+    not(target_arch = "wasm32"),
+    derive(bevy_reflect::Reflect, bevy_reflect::FromReflect)
+)]
+pub struct Rating {
+    /// The most clears that can be reached within the move budget
+    pub target: u8,
+    /// The fewest moves that reach `target`
+    pub moves: usize,
+    /// How many moves there are to choose from at each step of the way to `target`, on average,
+    /// that still leave it within reach in the fewest moves. 0 if there's nothing to solve.
+    pub branching: f32,
+    /// How many different shortest solutions there are, counting moves that could be made in
+    /// either order only once
+    pub solutions: usize,
+    /// How many first moves fall one clear short: they can't reach `target` in time any more,
+    /// but can still get within one clear of it
+    pub traps: usize,
+    /// The overall difficulty. 0 means there's nothing to solve, and each extra point is about
+    /// twice as hard.
+    pub score: f32,
+}
+
+/// Rates `board`, looking for the best that can be done within `max_moves` moves
+pub fn rate(board: &Board, max_moves: usize) -> Rating {
+    let (target, solution) = solver::best_within(board, max_moves);
+    let moves = solution.len();
+    if moves == 0 {
+        return Rating {
+            target,
+            moves,
+            branching: 0.0,
+            solutions: 1,
+            traps: 0,
+            score: 0.0,
+        };
+    }
+
+    // Walk the solution, counting the moves at each step that would have done just as well
+    let choices = solver::all_moves(board).count();
+    let mut position = *board;
+    let mut good_moves = 0;
+    let mut score = 0.0;
+    for (i, &mv) in solution.iter().enumerate() {
+        let remaining = moves - i - 1;
+        let good = solver::all_moves(&position)
+            .filter(|&candidate| {
+                let mut after = position;
+                after.apply(candidate);
+                solver::solve(&after, target, remaining).is_some()
+            })
+            .count();
+        good_moves += good;
+        score += (choices as f32 / good as f32).log2();
+        position.apply(mv);
+    }
+
+    let solutions = solver::count_solutions(board, target, moves);
+    let traps = if target > 1 {
+        solver::all_moves(board)
+            .filter(|&mv| {
+                let mut after = *board;
+                after.apply(mv);
+                solver::solve(&after, target, moves - 1).is_none()
+                    && solver::solve(&after, target - 1, moves - 1).is_some()
+            })
+            .count()
+    } else {
+        0
+    };
+
+    Rating {
+        target,
+        moves,
+        branching: good_moves as f32 / moves as f32,
+        solutions,
+        traps,
+        score: score + (1.0 + traps as f32).log2(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::test_board;
+
+    #[test]
+    fn nothing_to_solve_scores_zero() {
+        let rating = rate(&test_board(&["ABCD", "BCDA", "CDAB", "DABC"]), 1);
+        assert_eq!(
+            (rating.moves, rating.score, rating.branching),
+            (0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn branching_counts_the_moves_that_keep_the_target_in_reach() {
+        let few = rate(&test_board(&["ABCA", "BACC", "CCBA", "ABCA"]), 2);
+        let many = rate(&test_board(&["ABAC", "CDBD", "ABCA", "DCDB"]), 2);
+        assert_eq!((few.target, few.moves), (6, 2));
+        assert_eq!((many.target, many.moves), (1, 2));
+        // The last move of a solution always keeps the target in reach, so it's at least one
+        // good move per step, and well under the 24 there are to choose from
+        assert_eq!(few.branching, 1.5);
+        assert!(many.branching > few.branching && many.branching < 24.0);
+        assert!(many.score < few.score);
+    }
+}
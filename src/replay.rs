@@ -0,0 +1,358 @@
+// Recording and replaying play sessions. Launching with `--record <name>` writes down the seed,
+// the board settings and every move, rotation, reset, undo and redo as they happen, along with
+// when they happened. Launching with `--replay <name>` plays them back through the same systems
+// that handle the player's own input, which reproduces the session exactly. Recordings are kept
+// in `crate::storage` as `replays/<name>`.
+//
+// Nothing else is recorded, so anything else that changes the board, like its size or loading a
+// puzzle, is switched off while a recording is being made.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use yoco_test_kitchen::{
+    board::{Board, Move},
+    pieces::PieceDefinitions,
+};
+
+use crate::{
+    animation_in_progress, options::launch_option, settings::BoardSettings, storage, BoardState,
+    Direction, PieceQuery,
+};
+
+// How often a recording is saved while actions are coming in. Writing the whole recording out
+// after every action would get slower and slower as it grows.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum RecordedAction {
+    Move(Direction),
+    Rotate(Direction),
+    /// Putting the cursor straight onto a piece with the mouse or a touch
+    PlaceCursor {
+        x: u8,
+        y: u8,
+    },
+    /// Dragging a line around with the mouse or a touch
+    Slide(Move),
+    Reset,
+    Undo,
+    Redo,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct TimedAction {
+    /// Seconds since the first frame of play
+    time: f32,
+    action: RecordedAction,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Recording {
+    seed: u64,
+    width: usize,
+    height: usize,
+    /// The letters of the kinds of piece in play, as used in puzzle codes
+    pieces: String,
+    actions: Vec<TimedAction>,
+}
+
+#[derive(Clone, Debug)]
+enum Mode {
+    Off,
+    /// Recording to the file, or local storage entry, with this name
+    Recording(String),
+    /// Playing back, with the index of the next action to play
+    Playing(usize),
+}
+
+#[derive(Resource, Debug)]
+pub struct Replay {
+    mode: Mode,
+    recording: Recording,
+    // When the first frame of play happened, which recorded times are measured from
+    started: Option<Duration>,
+    // When the recording was last saved, if anything has been recorded since
+    unsaved_since: Option<Duration>,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Replay {
+            mode: Mode::Off,
+            recording: Recording {
+                seed: 0,
+                width: 0,
+                height: 0,
+                pieces: String::new(),
+                actions: vec![],
+            },
+            started: None,
+            unsaved_since: None,
+        }
+    }
+}
+
+/// An action being played back from a recording, for whichever system handles it to carry out
+pub struct Replayed(pub RecordedAction);
+
+/// An action the player has just carried out, for the recording if one is being made
+pub struct Recorded(pub RecordedAction);
+
+impl Replay {
+    /// Starts recording or playing back if whoever launched the game asked for it. Playing back
+    /// replaces `settings` and `seed` with the ones the recording was made with.
+    pub fn from_launch_options(
+        settings: &mut BoardSettings,
+        seed: &mut u64,
+        pieces: &PieceDefinitions,
+    ) -> Self {
+        if let Some(name) = launch_option("replay") {
+            match load(&name, pieces) {
+                Ok((recording, recorded_settings)) => {
+                    info!(
+                        "Replaying {} with {} actions",
+                        name,
+                        recording.actions.len()
+                    );
+                    *settings = recorded_settings;
+                    *seed = recording.seed;
+                    return Replay {
+                        mode: Mode::Playing(0),
+                        recording,
+                        ..default()
+                    };
+                }
+                Err(e) => warn!("Couldn't load the replay {}: {}", name, e),
+            }
+        }
+
+        let Some(name) = launch_option("record") else {
+            return Replay::default();
+        };
+        let replay = Replay {
+            recording: Recording {
+                seed: *seed,
+                width: settings.width,
+                height: settings.height,
+                pieces: settings
+                    .pieces
+                    .iter()
+                    .map(|&piece| pieces.code_char(piece))
+                    .collect(),
+                actions: vec![],
+            },
+            mode: Mode::Recording(name),
+            ..default()
+        };
+        // Save straight away, so a problem saving shows up before there's anything to lose
+        replay.save();
+        replay
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.mode, Mode::Playing(_))
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.mode, Mode::Recording(_))
+    }
+
+    /// Writes down an action that's just been carried out, if a recording is being made
+    fn record(&mut self, action: RecordedAction, now: Duration) {
+        if !self.is_recording() {
+            return;
+        }
+        let time = now - self.started.unwrap_or(now);
+        self.recording.actions.push(TimedAction {
+            time: time.as_secs_f32(),
+            action,
+        });
+        self.unsaved_since.get_or_insert(now);
+    }
+
+    fn save(&self) {
+        let Mode::Recording(name) = &self.mode else {
+            return;
+        };
+        let saved = ron::ser::to_string_pretty(&self.recording, ron::ser::PrettyConfig::default())
+            .expect("recordings can always be written as ron");
+        if let Err(e) = storage::write(&storage_name(name), &saved) {
+            warn!("Couldn't save the recording to {}", e);
+        }
+    }
+}
+
+// Whatever's still waiting to be saved when the game closes
+impl Drop for Replay {
+    fn drop(&mut self) {
+        if self.unsaved_since.is_some() {
+            self.save();
+        }
+    }
+}
+
+fn storage_name(name: &str) -> String {
+    format!("replays/{}", name)
+}
+
+fn load(name: &str, pieces: &PieceDefinitions) -> Result<(Recording, BoardSettings), String> {
+    let saved = storage::read(&storage_name(name)).ok_or("there's no recording with that name")?;
+    parse(&saved, pieces)
+}
+
+// Reads a saved recording, and checks it can be played back on this build
+fn parse(saved: &str, pieces: &PieceDefinitions) -> Result<(Recording, BoardSettings), String> {
+    let recording: Recording = ron::from_str(saved).map_err(|e| e.to_string())?;
+    if !Board::is_valid_size(recording.width, recording.height) {
+        return Err(format!(
+            "it's for a {}x{} board, which isn't a valid size",
+            recording.width, recording.height
+        ));
+    }
+    // The same rules as choosing pieces on the command line, so a recording can't set up a game
+    // that couldn't otherwise be played
+    let recorded_pieces = pieces
+        .choose(&recording.pieces)
+        .map_err(|e| e.to_string())?;
+    let settings = BoardSettings {
+        width: recording.width,
+        height: recording.height,
+        pieces: recorded_pieces,
+    };
+    Ok((recording, settings))
+}
+
+/// Hands out the recorded actions as their time comes. Each one also waits for the board to be
+/// filled in with nothing moving, the same as the player's own input does, so a slow frame can
+/// hold the replay up but never change how it turns out.
+pub fn play_back(
+    mut replay: ResMut<Replay>,
+    time: Res<Time>,
+    board_state: Res<BoardState>,
+    piece_query: PieceQuery,
+    mut replayed: EventWriter<Replayed>,
+) {
+    let now = time.elapsed();
+    let started = match replay.started {
+        Some(started) => started,
+        None => *replay.started.insert(now),
+    };
+    let Mode::Playing(next) = replay.mode else {
+        return;
+    };
+    let Some(&TimedAction { time, action }) = replay.recording.actions.get(next) else {
+        info!("Replay finished");
+        replay.mode = Mode::Off;
+        return;
+    };
+    if (now - started).as_secs_f32() < time
+        || animation_in_progress(&piece_query)
+        || board_state.has_empty()
+    {
+        return;
+    }
+    replayed.send(Replayed(action));
+    replay.mode = Mode::Playing(next + 1);
+}
+
+/// Writes down whatever the player's done this frame, and saves the recording every so often so
+/// not much is lost if the game crashes. Everything else only sends [`Recorded`] events, so it can
+/// look at the replay alongside [`Actions`](crate::controls::Actions) without needing to change it.
+pub fn record_actions(
+    time: Res<Time>,
+    mut recorded: EventReader<Recorded>,
+    mut replay: ResMut<Replay>,
+) {
+    for Recorded(action) in recorded.iter() {
+        replay.record(*action, time.elapsed());
+    }
+    let Some(unsaved_since) = replay.unsaved_since else {
+        return;
+    };
+    if time.elapsed() - unsaved_since >= SAVE_INTERVAL {
+        replay.save();
+        replay.unsaved_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yoco_test_kitchen::board::Line;
+
+    fn pieces() -> PieceDefinitions {
+        PieceDefinitions::from_ron(include_str!("../assets/pieces.ron")).unwrap()
+    }
+
+    fn recording() -> Recording {
+        Recording {
+            seed: 0xdead_beef_1234,
+            width: 5,
+            height: 4,
+            pieces: "MCDF".to_string(),
+            actions: vec![
+                TimedAction {
+                    time: 0.5,
+                    action: RecordedAction::Rotate(Direction::Left),
+                },
+                TimedAction {
+                    time: 1.25,
+                    action: RecordedAction::PlaceCursor { x: 3, y: 1 },
+                },
+                TimedAction {
+                    time: 2.0,
+                    action: RecordedAction::Slide(Move::new(Line::Col(3), -1)),
+                },
+                TimedAction {
+                    time: 2.5,
+                    action: RecordedAction::Undo,
+                },
+            ],
+        }
+    }
+
+    fn saved(recording: &Recording) -> String {
+        ron::ser::to_string_pretty(recording, ron::ser::PrettyConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn recordings_load_back_the_way_they_were_saved() {
+        let pieces = pieces();
+        let recording = recording();
+        let (loaded, settings) = parse(&saved(&recording), &pieces).unwrap();
+        assert_eq!(loaded, recording);
+        assert_eq!((settings.width, settings.height), (5, 4));
+        let letters: String = settings
+            .pieces
+            .iter()
+            .map(|&piece| pieces.code_char(piece))
+            .collect();
+        assert_eq!(letters, "MCDF");
+    }
+
+    #[test]
+    fn bad_recordings_are_rejected() {
+        let pieces = pieces();
+        assert!(parse("", &pieces).is_err());
+        assert!(parse("(seed: 1, width: 5)", &pieces).is_err());
+        assert!(parse(&saved(&recording())[..40], &pieces).is_err());
+
+        let wrong_size = Recording {
+            width: 100,
+            ..recording()
+        };
+        assert!(parse(&saved(&wrong_size), &pieces).is_err());
+        let unknown_piece = Recording {
+            pieces: "MCX".to_string(),
+            ..recording()
+        };
+        assert!(parse(&saved(&unknown_piece), &pieces).is_err());
+        let too_few_pieces = Recording {
+            pieces: "MC".to_string(),
+            ..recording()
+        };
+        assert!(parse(&saved(&too_few_pieces), &pieces).is_err());
+    }
+}
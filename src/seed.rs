@@ -0,0 +1,69 @@
+use bevy::prelude::*;
+use yoco_test_kitchen::{board::Piece, daily::StableRng};
+
+use crate::options::launch_option;
+
+/// Random number generator used for filling in the board. Everything it produces follows from
+/// the seed, so the same seed always gives the same starting board and the same refills, on any
+/// build (see [`StableRng`]).
+#[derive(Resource, Debug)]
+pub struct BoardRng {
+    pub seed: u64,
+    rng: StableRng,
+    // Whether anything has been drawn since the generator was seeded
+    used: bool,
+}
+
+impl BoardRng {
+    pub fn new(seed: u64) -> Self {
+        BoardRng {
+            seed,
+            rng: StableRng::new(seed),
+            used: false,
+        }
+    }
+
+    /// Gets ready to deal a whole new board. The first board is dealt straight from the seed, and
+    /// every board after that gets a new seed drawn from the one before, so the seed on show
+    /// always deals the board on show.
+    pub fn start_board(&mut self) {
+        if self.used {
+            *self = BoardRng::new(self.rng.next_u64());
+        }
+    }
+
+    /// One of `pieces`, picked at random
+    pub fn piece(&mut self, pieces: &[Piece]) -> Piece {
+        self.used = true;
+        pieces[self.rng.below(pieces.len())]
+    }
+}
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct SeedText;
+
+pub fn update_seed_text(board_rng: Res<BoardRng>, mut query: Query<&mut Text, With<SeedText>>) {
+    if !board_rng.is_changed() {
+        return;
+    }
+    let seed = board_rng.seed.to_string();
+    let mut text = query.single_mut();
+    if text.sections[1].value != seed {
+        text.sections[1].value = seed;
+    }
+}
+
+/// The seed asked for by whoever launched the game, or a random one if they didn't ask
+pub fn initial_seed() -> u64 {
+    match launch_option("seed") {
+        Some(seed) => match seed.parse() {
+            Ok(seed) => seed,
+            Err(_) => {
+                warn!("Ignoring invalid seed {:?}", seed);
+                rand::random()
+            }
+        },
+        None => rand::random(),
+    }
+}
@@ -0,0 +1,197 @@
+use bevy::prelude::*;
+use yoco_test_kitchen::{
+    board::{Board, Piece, MAX_BOARD_SIZE, MIN_BOARD_SIZE},
+    pieces::{PieceDefinitions, MIN_PIECE_COUNT},
+};
+
+use crate::{
+    controls::{Action, Actions},
+    loading::Pieces,
+    options::launch_option,
+    spawn_borders,
+    stats::NewBoard,
+    BoardLocation, BoardState, BorderMarker, History, PlayerCursor, TargetClears,
+};
+
+const DEFAULT_BOARD_SIZE: usize = 5;
+
+/// How many kinds of piece are in play for easy, normal and hard games. Hard games use every
+/// kind there is, however many that is.
+const PIECE_COUNT_PRESETS: [usize; 3] = [3, 6, usize::MAX];
+const DEFAULT_PIECE_COUNT: usize = 6;
+
+/// How the game is set up. Changing these in the middle of a game starts a new board.
+#[derive(Resource, Debug)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct BoardSettings {
+    pub width: usize,
+    pub height: usize,
+    /// The kinds of piece that new boards are filled with
+    pub pieces: Vec<Piece>,
+}
+
+impl BoardSettings {
+    /// The settings asked for by whoever launched the game, e.g. `--size 6x4 --pieces 3`
+    pub fn from_launch_options(pieces: &PieceDefinitions) -> Self {
+        let mut settings = BoardSettings {
+            width: DEFAULT_BOARD_SIZE,
+            height: DEFAULT_BOARD_SIZE,
+            pieces: pieces.first(DEFAULT_PIECE_COUNT),
+        };
+        if let Some(size) = launch_option("size") {
+            match parse_size(&size) {
+                Some((width, height)) => {
+                    settings.width = width;
+                    settings.height = height;
+                }
+                None => warn!(
+                    "Ignoring invalid board size {:?}, sizes go from {min}x{min} to {max}x{max}",
+                    size,
+                    min = MIN_BOARD_SIZE,
+                    max = MAX_BOARD_SIZE,
+                ),
+            }
+        }
+        if let Some(chosen) = launch_option("pieces") {
+            match pieces.choose(&chosen) {
+                Ok(chosen) => settings.pieces = chosen,
+                Err(e) => warn!(
+                    "Ignoring invalid pieces {:?}: {}. Give either a number from {} to {} or the \
+                     letters of the pieces to use",
+                    chosen,
+                    e,
+                    MIN_PIECE_COUNT,
+                    pieces.len(),
+                ),
+            }
+        }
+        settings
+    }
+}
+
+fn parse_size(size: &str) -> Option<(usize, usize)> {
+    let (width, height) = size.split_once('x')?;
+    let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+    Board::is_valid_size(width, height).then_some((width, height))
+}
+
+/// A board to load once the board has been resized to fit it
+#[derive(Resource, Debug, Default)]
+pub struct NextBoard(pub Option<Board>);
+
+pub fn change_board_size(actions: Actions, mut settings: ResMut<BoardSettings>) {
+    let (width, height) = (settings.width, settings.height);
+    let (width, height) = if actions.just_pressed(Action::Narrower) {
+        (width.saturating_sub(1), height)
+    } else if actions.just_pressed(Action::Wider) {
+        (width + 1, height)
+    } else if actions.just_pressed(Action::Shorter) {
+        (width, height.saturating_sub(1))
+    } else if actions.just_pressed(Action::Taller) {
+        (width, height + 1)
+    } else {
+        return;
+    };
+
+    if Board::is_valid_size(width, height) {
+        settings.width = width;
+        settings.height = height;
+    }
+}
+
+// Steps through the easy, normal and hard piece counts
+pub fn change_piece_count(
+    actions: Actions,
+    pieces: Res<Pieces>,
+    mut settings: ResMut<BoardSettings>,
+) {
+    if !actions.just_pressed(Action::PieceCount) {
+        return;
+    }
+    let count = PIECE_COUNT_PRESETS
+        .iter()
+        .map(|&count| count.min(pieces.len()))
+        .find(|&count| count > settings.pieces.len())
+        .unwrap_or(PIECE_COUNT_PRESETS[0]);
+    settings.pieces = pieces.first(count);
+}
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct PieceCountText;
+
+pub fn update_piece_count_text(
+    settings: Res<BoardSettings>,
+    pieces: Res<Pieces>,
+    mut query: Query<&mut Text, With<PieceCountText>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let letters: String = settings
+        .pieces
+        .iter()
+        .map(|&piece| pieces.code_char(piece))
+        .collect();
+    query.single_mut().sections[1].value = format!("{} ({})", settings.pieces.len(), letters);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn apply_board_settings(
+    mut commands: Commands,
+    settings: Res<BoardSettings>,
+    mut board_state: ResMut<BoardState>,
+    mut next_board: ResMut<NextBoard>,
+    mut history: ResMut<History>,
+    mut target_clears: ResMut<TargetClears>,
+    mut new_boards: EventWriter<NewBoard>,
+    clear_color: Res<ClearColor>,
+    border_query: Query<Entity, With<BorderMarker>>,
+    mut player_query: Query<(&mut BoardLocation, &mut Transform), With<PlayerCursor>>,
+) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+    if (settings.width, settings.height) == (board_state.width(), board_state.height()) {
+        // Only the pieces in play changed, so start over with a board made of them
+        board_state.clear();
+        target_clears.0 = None;
+        *history = History::default();
+        return;
+    }
+
+    board_state.despawn(&mut commands);
+    for entity in border_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let mut new_state = BoardState::empty(
+        &mut commands,
+        board_state.texture_atlas_handle.clone(),
+        settings.width,
+        settings.height,
+    );
+    if let Some(board) = next_board.0.take() {
+        new_state.fill(&mut commands, &board);
+        new_boards.send(NewBoard(board));
+    }
+    spawn_borders(
+        &mut commands,
+        clear_color.0,
+        settings.width,
+        settings.height,
+    );
+
+    let (mut board_location, mut transform) = player_query.single_mut();
+    board_location.x = (settings.width / 2) as u8;
+    board_location.y = (settings.height / 2) as u8;
+    let world_pos =
+        new_state.piece_location_to_world_coords(board_location.x as i8, board_location.y as i8);
+    transform.translation.x = world_pos.x;
+    transform.translation.y = world_pos.y;
+
+    commands.insert_resource(new_state);
+
+    // Moves and boards from before don't fit the new board
+    *history = History::default();
+}
@@ -0,0 +1,156 @@
+use bevy::prelude::*;
+use yoco_test_kitchen::puzzle_code::Puzzle;
+
+use crate::{
+    animation_in_progress,
+    controls::{Action, Actions},
+    loading::Pieces,
+    settings::{BoardSettings, NextBoard},
+    stats::NewBoard,
+    update_piece_sprites, BoardState, History, HistoryEntry, PieceQuery, TargetClears,
+};
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct StatusText;
+
+/// Asks for a puzzle to be put on the board in place of the current one
+pub struct LoadPuzzle(pub Puzzle);
+
+pub fn show_status(status_query: &mut Query<&mut Text, With<StatusText>>, status: &str) {
+    info!("{}", status);
+    status_query.single_mut().sections[0].value = format!(" {}", status);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn copy_paste_puzzle(
+    actions: Actions,
+    mut clipboard: Local<Clipboard>,
+    pieces: Res<Pieces>,
+    board_state: Res<BoardState>,
+    target_clears: Res<TargetClears>,
+    mut load_puzzles: EventWriter<LoadPuzzle>,
+    piece_query: PieceQuery,
+    mut status_query: Query<&mut Text, With<StatusText>>,
+) {
+    if animation_in_progress(&piece_query) {
+        return;
+    }
+    let Some(board) = board_state.board() else {
+        return;
+    };
+
+    let status = if actions.just_pressed(Action::CopyPuzzle) {
+        let code = Puzzle {
+            board,
+            target_clears: target_clears.0,
+        }
+        .to_code(&pieces);
+        match clipboard.copy(&code) {
+            Ok(()) => format!("Copied puzzle code {}", code),
+            Err(e) => format!("Couldn't copy puzzle code: {}", e),
+        }
+    } else if actions.just_pressed(Action::PastePuzzle) {
+        match clipboard.paste() {
+            Ok(Some(code)) => match Puzzle::from_code(&code, &pieces) {
+                Ok(puzzle) => {
+                    load_puzzles.send(LoadPuzzle(puzzle));
+                    format!("Loaded puzzle code {}", puzzle.to_code(&pieces))
+                }
+                Err(e) => format!("Invalid puzzle code: {}", e),
+            },
+            Ok(None) => return,
+            Err(e) => format!("Couldn't paste puzzle code: {}", e),
+        }
+    } else {
+        return;
+    };
+
+    show_status(&mut status_query, &status);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn load_puzzle(
+    mut reader: EventReader<LoadPuzzle>,
+    mut board_state: ResMut<BoardState>,
+    mut settings: ResMut<BoardSettings>,
+    mut next_board: ResMut<NextBoard>,
+    mut target_clears: ResMut<TargetClears>,
+    mut history: ResMut<History>,
+    mut new_boards: EventWriter<NewBoard>,
+    mut piece_query: PieceQuery,
+) {
+    let Some(LoadPuzzle(puzzle)) = reader.iter().last() else {
+        return;
+    };
+
+    let width = puzzle.board.width();
+    let height = puzzle.board.height();
+    if (width, height) == (board_state.width(), board_state.height()) {
+        if let Some(board) = board_state.board() {
+            history.record(HistoryEntry::Board(board));
+        }
+        board_state.set_board(&puzzle.board);
+        update_piece_sprites(&board_state, &mut piece_query);
+        new_boards.send(NewBoard(puzzle.board));
+    } else {
+        // The board has to be rebuilt at the new size before it can be loaded
+        settings.width = width;
+        settings.height = height;
+        next_board.0 = Some(puzzle.board);
+    }
+    target_clears.0 = puzzle.target_clears;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+pub struct Clipboard(Option<arboard::Clipboard>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Clipboard {
+    // On Linux the copied text only stays available while the clipboard that copied it is alive,
+    // so hang on to it instead of making a new one every time.
+    fn get(&mut self) -> Result<&mut arboard::Clipboard, arboard::Error> {
+        if self.0.is_none() {
+            self.0 = Some(arboard::Clipboard::new()?);
+        }
+        Ok(self.0.as_mut().unwrap())
+    }
+
+    fn copy(&mut self, text: &str) -> Result<(), String> {
+        self.get()
+            .and_then(|clipboard| clipboard.set_text(text))
+            .map_err(|e| e.to_string())
+    }
+
+    fn paste(&mut self) -> Result<Option<String>, String> {
+        self.get()
+            .and_then(|clipboard| clipboard.get_text())
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+}
+
+// Browsers only offer an asynchronous clipboard API, and only to pages that ask for permission,
+// so the web build uses prompts instead
+#[cfg(target_arch = "wasm32")]
+#[derive(Default)]
+pub struct Clipboard;
+
+#[cfg(target_arch = "wasm32")]
+impl Clipboard {
+    fn copy(&mut self, text: &str) -> Result<(), String> {
+        web_sys::window()
+            .ok_or("no browser window")?
+            .prompt_with_message_and_default("Copy this puzzle code:", text)
+            .map(|_| ())
+            .map_err(|_| "the browser blocked the prompt".to_owned())
+    }
+
+    fn paste(&mut self) -> Result<Option<String>, String> {
+        web_sys::window()
+            .ok_or("no browser window")?
+            .prompt_with_message("Paste a puzzle code:")
+            .map_err(|_| "the browser blocked the prompt".to_owned())
+    }
+}
@@ -0,0 +1,231 @@
+// Finds the shortest sequence of rotations that takes a board to a given number of clears.
+//
+// This is an iterative-deepening A* search over every rotation of every row and column. A
+// rotation counts as a single move no matter how many steps it shifts the line by.
+
+use std::collections::HashMap;
+
+use crate::board::{Board, Line, Move};
+
+/// Every distinct rotation that can be made on a board. Steps are kept as close to zero as
+/// possible so they read naturally, e.g. -1 rather than +4 on a five piece row.
+pub fn all_moves(board: &Board) -> impl Iterator<Item = Move> + '_ {
+    board.lines().flat_map(|line| {
+        let len = board.line_len(line) as isize;
+        (-(len - 1) / 2..=len / 2)
+            .filter(|steps| *steps != 0)
+            .map(move |steps| Move::new(line, steps))
+    })
+}
+
+/// Returns the shortest list of moves that leaves the board with at least `target` clears, or
+/// None if that can't be done in `max_moves` moves or fewer.
+pub fn solve(board: &Board, target: u8, max_moves: usize) -> Option<Vec<Move>> {
+    if board.count_clears() >= target {
+        return Some(vec![]);
+    }
+
+    let mut search = Search {
+        target,
+        seen: HashMap::new(),
+        path: vec![],
+    };
+    for depth in 1..=max_moves {
+        search.seen.clear();
+        if search.search(*board, depth) {
+            return Some(search.path);
+        }
+    }
+    None
+}
+
+/// Finds the most clears that can be reached in `max_moves` moves or fewer, along with the
+/// shortest list of moves that gets there.
+pub fn best_within(board: &Board, max_moves: usize) -> (u8, Vec<Move>) {
+    let mut best = (board.count_clears(), vec![]);
+    while let Some(moves) = solve(board, best.0 + 1, max_moves) {
+        let mut after = *board;
+        for mv in &moves {
+            after.apply(*mv);
+        }
+        best = (after.count_clears(), moves);
+    }
+    best
+}
+
+/// Counts the different lists of exactly `moves` moves that leave the board with at least `target`
+/// clears. Moves that could be made in either order, like rotations of two different rows, are
+/// only counted in one order. Lists that reach the target early and keep going aren't counted, so
+/// with `moves` being the fewest it takes, this is the number of shortest solutions.
+pub fn count_solutions(board: &Board, target: u8, moves: usize) -> usize {
+    let mut counted = HashMap::new();
+    count_from(*board, target, moves, None, &mut counted)
+}
+
+fn count_from(
+    board: Board,
+    target: u8,
+    remaining: usize,
+    prev_line: Option<Line>,
+    counted: &mut HashMap<(Board, Option<Line>, usize), usize>,
+) -> usize {
+    if board.count_clears() >= target {
+        return usize::from(remaining == 0);
+    }
+    if remaining == 0 || lower_bound(&board) > remaining {
+        return 0;
+    }
+    if let Some(&count) = counted.get(&(board, prev_line, remaining)) {
+        return count;
+    }
+
+    let count = all_moves(&board)
+        .filter(|mv| follows(prev_line, mv.line))
+        .map(|mv| {
+            let mut next = board;
+            next.apply(mv);
+            count_from(next, target, remaining - 1, Some(mv.line), counted)
+        })
+        .sum();
+    counted.insert((board, prev_line, remaining), count);
+    count
+}
+
+struct Search {
+    target: u8,
+    // The most moves we had left the last time we reached a board. The line of the previous move
+    // is part of the key because it limits which moves get tried next.
+    seen: HashMap<(Board, Option<Line>), usize>,
+    path: Vec<Move>,
+}
+
+impl Search {
+    fn search(&mut self, board: Board, remaining: usize) -> bool {
+        if board.count_clears() >= self.target {
+            return true;
+        }
+        if remaining == 0 || lower_bound(&board) > remaining {
+            return false;
+        }
+
+        let prev_line = self.path.last().map(|mv| mv.line);
+        match self.seen.get(&(board, prev_line)) {
+            Some(&seen_remaining) if seen_remaining >= remaining => return false,
+            _ => {}
+        }
+        self.seen.insert((board, prev_line), remaining);
+
+        for mv in all_moves(&board) {
+            if !follows(prev_line, mv.line) {
+                continue;
+            }
+            let mut next = board;
+            next.apply(mv);
+            self.path.push(mv);
+            if self.search(next, remaining - 1) {
+                return true;
+            }
+            self.path.pop();
+        }
+        false
+    }
+}
+
+// Rotating the same line twice in a row is the same as rotating it once, and rotations of two
+// different rows (or two different columns) can happen in either order, so only one order is
+// searched.
+fn follows(prev: Option<Line>, next: Line) -> bool {
+    match (prev, next) {
+        (Some(Line::Row(a)), Line::Row(b)) => a < b,
+        (Some(Line::Col(a)), Line::Col(b)) => a < b,
+        _ => true,
+    }
+}
+
+// Any number of clears needs at least one row or column made up of a single piece. Rotating a
+// row never changes which pieces it holds and rotating a column changes at most one piece in any
+// given row, so a row needs at least one move for every piece that doesn't match its most common
+// piece. The same goes for columns.
+fn lower_bound(board: &Board) -> usize {
+    board
+        .lines()
+        .map(|line| {
+            let pieces = board.line_pieces(line);
+            let most_common = pieces
+                .iter()
+                .map(|p| pieces.iter().filter(|q| p == *q).count())
+                .max()
+                .unwrap_or(0);
+            pieces.len() - most_common
+        })
+        .min()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::test_board;
+
+    // Tries every list of `moves` moves, without any of the solver's pruning
+    fn reachable(board: &Board, target: u8, moves: usize) -> bool {
+        board.count_clears() >= target
+            || moves > 0
+                && all_moves(board).any(|mv| {
+                    let mut next = *board;
+                    next.apply(mv);
+                    reachable(&next, target, moves - 1)
+                })
+    }
+
+    fn assert_shortest(board: &Board, target: u8, expected_len: usize) {
+        let moves = solve(board, target, 5).expect("no solution found");
+        assert_eq!(moves.len(), expected_len);
+        let mut after = *board;
+        for mv in &moves {
+            after.apply(*mv);
+        }
+        assert!(after.count_clears() >= target);
+        assert!(!reachable(board, target, expected_len - 1));
+    }
+
+    #[test]
+    fn cleared_board_needs_no_moves() {
+        let b = test_board(&["AAAA", "ABCD", "BCDA", "CDAB"]);
+        assert_eq!(solve(&b, 1, 3), Some(vec![]));
+        assert_eq!(best_within(&b, 0), (1, vec![]));
+    }
+
+    #[test]
+    fn finds_shortest_solutions() {
+        let square = test_board(&["ABAC", "CDBD", "ABCA", "DCDB"]);
+        assert_shortest(&square, 1, 2);
+        assert_shortest(&square, 2, 3);
+        let five = test_board(&["ABCDE", "BCADE", "EDABC", "CABED", "DEBCA"]);
+        assert_shortest(&five, 1, 3);
+    }
+
+    #[test]
+    fn respects_the_move_limit() {
+        let b = test_board(&["ABAC", "CDBD", "ABCA", "DCDB"]);
+        assert_eq!(solve(&b, 2, 2), None);
+        assert_eq!(best_within(&b, 2).0, 1);
+        let (clears, moves) = best_within(&b, 3);
+        assert_eq!((clears, moves.len()), (8, 3));
+    }
+
+    #[test]
+    fn counts_every_single_move_solution() {
+        let mut b = test_board(&["ABAC", "CDBD", "ABCA", "DCDB"]);
+        b.apply(Move::new(Line::Row(0), 2));
+        let by_hand = all_moves(&b)
+            .filter(|&mv| {
+                let mut next = b;
+                next.apply(mv);
+                next.has_clear()
+            })
+            .count();
+        assert!(by_hand > 0);
+        assert_eq!(count_solutions(&b, 1, 1), by_hand);
+    }
+}
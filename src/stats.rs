@@ -0,0 +1,99 @@
+// How the player is getting on with the current board: the moves they've made, how long they've
+// been at it, and how many moves the best possible result takes. The board's difficulty rating
+// can be shown alongside.
+
+use bevy::{prelude::*, time::Stopwatch};
+use bevy_tweening::TweenCompleted;
+use yoco_test_kitchen::{
+    board::Board,
+    rating::{self, Rating},
+};
+
+use crate::{
+    controls::{Action, Actions},
+    hint, move_count_change,
+};
+
+/// Sent whenever a different board is put in play, whether it was generated, loaded or brought
+/// back by undo
+pub struct NewBoard(pub Board);
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct Par {
+    pub clears: u8,
+    pub moves: usize,
+}
+
+#[derive(Resource, Debug, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct RoundStats {
+    pub moves: u32,
+    pub elapsed: Stopwatch,
+    /// The fewest moves to the most clears that can be reached, or None if the board can't be
+    /// improved on within as many moves as the hint looks ahead
+    pub par: Option<Par>,
+    pub rating: Option<Rating>,
+}
+
+/// Whether the board's difficulty rating is shown next to par
+#[derive(Resource, Debug, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct ShowDifficulty(pub bool);
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct StatsText;
+
+pub fn toggle_difficulty(actions: Actions, mut show: ResMut<ShowDifficulty>) {
+    if actions.just_pressed(Action::Difficulty) {
+        show.0 = !show.0;
+    }
+}
+
+pub fn update_round_stats(
+    mut new_boards: EventReader<NewBoard>,
+    show_difficulty: Res<ShowDifficulty>,
+    mut slides: EventReader<TweenCompleted>,
+    time: Res<Time>,
+    mut stats: ResMut<RoundStats>,
+    mut text_query: Query<&mut Text, With<StatsText>>,
+) {
+    if let Some(NewBoard(board)) = new_boards.iter().last() {
+        // Rating the board works out par along the way
+        let rating = rating::rate(board, hint::search_moves(board));
+        *stats = RoundStats {
+            par: (rating.target > board.count_clears()).then_some(Par {
+                clears: rating.target,
+                moves: rating.moves,
+            }),
+            rating: Some(rating),
+            ..default()
+        };
+    }
+    for event in slides.iter() {
+        stats.moves = stats.moves.saturating_add_signed(move_count_change(event));
+    }
+    stats.elapsed.tick(time.delta());
+
+    let seconds = stats.elapsed.elapsed().as_secs();
+    let par = match stats.par {
+        Some(par) => format!("{} for {} clears", par.moves, par.clears),
+        None => "-".to_owned(),
+    };
+    let mut value = format!(
+        " Moves: {}   Time: {}:{:02}   Par: {}",
+        stats.moves,
+        seconds / 60,
+        seconds % 60,
+        par
+    );
+    if let (true, Some(rating)) = (show_difficulty.0, stats.rating) {
+        value += &format!("   Difficulty: {:.1}", rating.score);
+    }
+    // Only touch the text when it changes, so it isn't laid out again every frame
+    let mut text = text_query.single_mut();
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
@@ -0,0 +1,62 @@
+// Small files the game keeps between runs, like the controls. Native builds keep them in the
+// config directory as `yoco_test_kitchen/<name>.ron`, and the web build keeps them in the
+// browser's local storage.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod platform {
+    use std::{fs, io, path::PathBuf};
+
+    fn path(name: &str) -> Option<PathBuf> {
+        Some(
+            dirs::config_dir()?
+                .join("yoco_test_kitchen")
+                .join(format!("{}.ron", name)),
+        )
+    }
+
+    pub fn read(name: &str) -> Option<String> {
+        let path = path(name)?;
+        match fs::read_to_string(&path) {
+            Ok(saved) => Some(saved),
+            // Nothing has been saved yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                bevy::log::warn!("Couldn't read {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn write(name: &str, saved: &str) -> Result<(), String> {
+        let path = path(name).ok_or("there's no config directory")?;
+        let write = || {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, saved)
+        };
+        write().map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod platform {
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    fn key(name: &str) -> String {
+        format!("yoco_test_kitchen.{}", name)
+    }
+
+    pub fn read(name: &str) -> Option<String> {
+        local_storage()?.get_item(&key(name)).ok()?
+    }
+
+    pub fn write(name: &str, saved: &str) -> Result<(), String> {
+        local_storage()
+            .ok_or("local storage isn't available")?
+            .set_item(&key(name), saved)
+            .map_err(|_| "the browser refused to store it".to_owned())
+    }
+}
+
+pub use platform::{read, write};
@@ -0,0 +1,279 @@
+// Time attack: make as many clears as possible before the countdown runs out. Lining up two or
+// more clears at once scores them and brings in a fresh board.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_tweening::TweenCompleted;
+
+use crate::{
+    campaign::CurrentLevel,
+    cascade::Cascade,
+    controls::{Action, Actions},
+    BoardState, History, TargetClears, PIECE_SLIDE_COMPLETED,
+};
+
+/// The round lengths to choose from, in seconds
+const ROUND_LENGTHS: [u64; 3] = [60, 120, 300];
+
+// A board only scores once it has at least this many clears on it
+const MIN_SCORING_CLEARS: u8 = 2;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+enum TimeAttackPhase {
+    // Not playing time attack at all
+    #[default]
+    Off,
+    // Showing the start screen
+    Menu,
+    Running,
+    // Showing the end of round summary
+    Finished,
+}
+
+#[derive(Resource, Debug, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct TimeAttack {
+    phase: TimeAttackPhase,
+    // Index into ROUND_LENGTHS
+    round_length: usize,
+    // Counts down the time left in the round
+    timer: Timer,
+    score: u32,
+    boards: u32,
+    moves: u32,
+}
+
+impl TimeAttack {
+    pub fn is_active(&self) -> bool {
+        !matches!(self.phase, TimeAttackPhase::Off)
+    }
+
+    /// False while one of the time attack screens is covering the board
+    pub fn accepts_input(&self) -> bool {
+        !matches!(
+            self.phase,
+            TimeAttackPhase::Menu | TimeAttackPhase::Finished
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct TimeAttackOverlay;
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct TimeAttackOverlayText;
+
+#[derive(Copy, Clone, Debug, Component)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Reflect, FromReflect))]
+pub struct TimeAttackTimerText;
+
+pub fn setup_time_attack(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let text_style = TextStyle {
+        font: asset_server.load("FiraSans-Bold.ttf"),
+        font_size: 30.0,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            TimeAttackOverlay,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("", text_style.clone())
+                    .with_text_alignment(TextAlignment::Center),
+                TimeAttackOverlayText,
+            ));
+        });
+
+    commands.spawn((
+        TextBundle::from_section("", text_style).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(0.0),
+                right: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        TimeAttackTimerText,
+    ));
+}
+
+// Moves between the start screen, the round itself and the summary
+pub fn time_attack_menu(
+    keys: Res<Input<KeyCode>>,
+    // Actions are limited by the round, so they're read before it can be changed
+    mut actions_and_time_attack: ParamSet<(Actions, ResMut<TimeAttack>)>,
+    mut cascade: ResMut<Cascade>,
+    mut board_state: ResMut<BoardState>,
+    mut target_clears: ResMut<TargetClears>,
+    mut history: ResMut<History>,
+    mut current_level: ResMut<CurrentLevel>,
+) {
+    let pressed = actions_and_time_attack
+        .p0()
+        .just_pressed(Action::TimeAttack);
+    let mut time_attack = actions_and_time_attack.p1();
+    match time_attack.phase {
+        TimeAttackPhase::Off => {
+            if pressed {
+                time_attack.phase = TimeAttackPhase::Menu;
+            }
+        }
+        TimeAttackPhase::Menu => {
+            let chosen = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3]
+                .into_iter()
+                .position(|key| keys.just_pressed(key));
+            if let Some(round_length) = chosen {
+                time_attack.round_length = round_length;
+            } else if keys.just_pressed(KeyCode::Return) {
+                let seconds = ROUND_LENGTHS[time_attack.round_length];
+                *time_attack = TimeAttack {
+                    phase: TimeAttackPhase::Running,
+                    round_length: time_attack.round_length,
+                    timer: Timer::new(Duration::from_secs(seconds), TimerMode::Once),
+                    ..default()
+                };
+                // Every round starts from a fresh board, and clears are left on the board for the
+                // round to score rather than being cascaded away. Any level in play is over, so it
+                // doesn't turn cascades back on when the round's board comes in.
+                current_level.abandon();
+                cascade.enabled = false;
+                target_clears.0 = None;
+                *history = History::default();
+                board_state.clear();
+            } else if keys.just_pressed(KeyCode::Escape) {
+                time_attack.phase = TimeAttackPhase::Off;
+            }
+        }
+        TimeAttackPhase::Running => {
+            if keys.just_pressed(KeyCode::Escape) {
+                time_attack.phase = TimeAttackPhase::Finished;
+            }
+        }
+        TimeAttackPhase::Finished => {
+            if keys.just_pressed(KeyCode::Return) {
+                time_attack.phase = TimeAttackPhase::Menu;
+            } else if keys.just_pressed(KeyCode::Escape) {
+                time_attack.phase = TimeAttackPhase::Off;
+            }
+        }
+    }
+}
+
+// Counts down, and scores the board each time a slide settles
+pub fn run_time_attack(
+    time: Res<Time>,
+    mut reader: EventReader<TweenCompleted>,
+    mut time_attack: ResMut<TimeAttack>,
+    mut board_state: ResMut<BoardState>,
+    mut history: ResMut<History>,
+) {
+    let slides = reader
+        .iter()
+        .filter(|event| event.user_data == PIECE_SLIDE_COMPLETED)
+        .count();
+    if time_attack.phase != TimeAttackPhase::Running {
+        return;
+    }
+    if time_attack.timer.tick(time.delta()).finished() {
+        time_attack.phase = TimeAttackPhase::Finished;
+        return;
+    }
+    if slides == 0 {
+        return;
+    }
+
+    time_attack.moves += slides as u32;
+    let Some(board) = board_state.board() else {
+        return;
+    };
+    let clears = board.count_clears();
+    if clears >= MIN_SCORING_CLEARS {
+        time_attack.score += clears as u32;
+        time_attack.boards += 1;
+        *history = History::default();
+        board_state.clear();
+    }
+}
+
+pub fn update_time_attack_text(
+    time_attack: Res<TimeAttack>,
+    mut overlay_query: Query<&mut Visibility, With<TimeAttackOverlay>>,
+    mut overlay_text_query: Query<&mut Text, With<TimeAttackOverlayText>>,
+    mut timer_text_query: Query<
+        &mut Text,
+        (With<TimeAttackTimerText>, Without<TimeAttackOverlayText>),
+    >,
+) {
+    if !time_attack.is_changed() {
+        return;
+    }
+
+    let overlay = match time_attack.phase {
+        TimeAttackPhase::Menu => Some(format!(
+            "Time attack\n\n\
+             Line up {} or more clears at once to score them\n\
+             and get a fresh board.\n\n\
+             Round length: {}\n\
+             1, 2 or 3 to change it\n\n\
+             Enter to start, Esc to go back",
+            MIN_SCORING_CLEARS,
+            format_time(ROUND_LENGTHS[time_attack.round_length]),
+        )),
+        TimeAttackPhase::Finished => Some(format!(
+            "Time's up!\n\n\
+             Score: {}\n\
+             Boards cleared: {}\n\
+             Moves: {}\n\n\
+             Enter to play again, Esc to go back",
+            time_attack.score, time_attack.boards, time_attack.moves,
+        )),
+        TimeAttackPhase::Off | TimeAttackPhase::Running => None,
+    };
+    *overlay_query.single_mut() = if overlay.is_some() {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    if let Some(overlay) = overlay {
+        overlay_text_query.single_mut().sections[0].value = overlay;
+    }
+
+    let timer = if time_attack.phase == TimeAttackPhase::Running {
+        // Round up, so the timer only shows 0:00 once time is actually up
+        let seconds = time_attack.timer.remaining().as_secs_f32().ceil() as u64;
+        format!(
+            "Time: {}   Score: {} ",
+            format_time(seconds),
+            time_attack.score
+        )
+    } else {
+        String::new()
+    };
+    let mut timer_text = timer_text_query.single_mut();
+    if timer_text.sections[0].value != timer {
+        timer_text.sections[0].value = timer;
+    }
+}
+
+fn format_time(seconds: u64) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}